@@ -0,0 +1,61 @@
+use byteorder::*;
+use serde::*;
+use crate::endian::Endian;
+
+/// Files starting with the magic `a001`.
+///
+/// Their content isn't known, but they look like a table of sections, in the byte order of their container:
+/// - 4 bytes: "a001"
+/// - 4 bytes: number of sections
+/// - 4 * sections bytes: offsets of the sections (relative to the start of the file)
+///
+/// If the offsets don't make sense, only the first words are kept.
+#[derive(Debug, Clone)]
+pub struct A001File {
+    pub header: Vec<u32>,
+    pub sections: Vec<A001Section>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct A001Section {
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl A001File {
+    pub fn read(buffer: &[u8], endian: Endian) -> Option<Self> {
+        if buffer.len() < 4 || BigEndian::read_u32(buffer) != crate::magic::A001 {
+            return None;
+        }
+
+        let header = buffer[4..].chunks_exact(4).take(4).map(|word| endian.u32_at(word)).collect();
+
+        Some(Self {
+            header,
+            sections: read_sections(buffer, endian).unwrap_or_default(),
+        })
+    }
+}
+
+fn read_sections(buffer: &[u8], endian: Endian) -> Option<Vec<A001Section>> {
+    if buffer.len() < 8 {
+        return None;
+    }
+
+    let count = endian.u32_at(&buffer[4..]) as usize;
+    let end_of_table = 8 + count * 4;
+    if count == 0 || end_of_table > buffer.len() {
+        return None;
+    }
+
+    let offsets: Vec<_> = buffer[8..end_of_table].chunks_exact(4).map(|w| endian.u32_at(w) as u64).collect();
+    if offsets[0] < end_of_table as u64 || offsets.windows(2).any(|w| w[0] > w[1]) || *offsets.last().unwrap() > buffer.len() as u64 {
+        return None;
+    }
+
+    let ends = offsets.iter().skip(1).copied().chain(std::iter::once(buffer.len() as u64));
+    Some(offsets.iter().zip(ends).map(|(offset, end)| A001Section {
+        offset: *offset,
+        size: end - offset,
+    }).collect())
+}
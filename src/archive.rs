@@ -3,15 +3,32 @@ use crate::Compression;
 use crate::TextureFormat;
 use crate::texture::HeaderConverter;
 use crate::texture;
-use image_dds::image::buffer::ConvertBuffer;
-use image_dds::image::io::Reader as ImageReader;
-use crate::image::*;
 use std::fs::File;
+use std::rc::Rc;
+use memmap2::Mmap;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use rayon::prelude::*;
+use std::path::{Component, Path, PathBuf};
+use std::collections::HashSet;
 use image_dds::image_from_dds;
 use crate::Dds;
 use byteorder::*;
 use std::io::*;
 use crate::Texture;
+use crate::Navmesh;
+use crate::MaterialTable;
+use crate::script;
+use crate::ScriptItem;
+use crate::MessageTable;
+use crate::Messages;
+use crate::MessageFormat;
+use crate::A001File;
+use crate::strings::*;
+use crate::magic::*;
+use crate::endian::Endian;
+use crate::game::*;
+use crate::layout::Layout;
+use crate::tree::*;
 use serde::*;
 
 #[derive(Debug)]
@@ -22,6 +39,7 @@ pub struct ChildData {
 
 #[derive(Debug)]
 pub struct ContainerHeader {
+    pub endian: Endian,
     pub version: u32,
     pub format: u32,
     pub size: u32,
@@ -32,6 +50,8 @@ pub struct ContainerHeader {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Container {
+    #[serde(default)]
+    pub endian: Endian,
     pub version: u32,
     pub format: u32,
     pub size: u32,
@@ -43,29 +63,196 @@ pub struct Container {
 pub enum ArchiveEntry {
     Container(Container),
     Textures(Vec<Texture>),
+    TextureBlock(TextureBlock),
     File(String),
     Files(Vec<String>),
+    NamedFiles(NamedFiles),
+    TextureSet(TextureSet),
+    Navmesh(String),
+    Script(String),
+    Messages(Messages),
+    Blob(Blob),
 }
 
-pub trait SeekRead: Read + Seek {}
-impl SeekRead for std::fs::File {}
+impl ArchiveEntry {
+    /// Files with their name in the string table, `Files` come from an older metadata.json.
+    fn named_files(&self) -> Option<NamedFiles> {
+        match self {
+            ArchiveEntry::Files(files) => Some(NamedFiles {
+                container: None,
+                strings: StringTableFormat::default(),
+                headerless: false,
+                files: files.iter().map(|file| NamedFile {
+                    name: file_name(file).to_string(),
+                    filename: file.clone(),
+                    messages: None,
+                }).collect(),
+            }),
+            ArchiveEntry::NamedFiles(files) => Some(files.clone()),
+            _ => None,
+        }
+    }
+
+    /// Textures with the format of their string table, `Textures` come from an older metadata.json.
+    fn texture_set(&self) -> Option<TextureSet> {
+        match self {
+            ArchiveEntry::Textures(textures) => Some(TextureSet {
+                container: None,
+                strings: StringTableFormat::default(),
+                headerless: false,
+                textures: textures.clone(),
+            }),
+            ArchiveEntry::TextureSet(set) => Some(set.clone()),
+            ArchiveEntry::TextureBlock(block) => Some(TextureSet {
+                container: None,
+                strings: StringTableFormat::default(),
+                headerless: block.headerless,
+                textures: block.textures.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Header of a container of format 0 that is written from the entry in it (texture sets of format 2, models of format 8).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct SubContainer {
+    pub endian: Endian,
+    pub version: u32,
+    pub size: u32,
+    pub alignment: u32,
+}
+
+impl SubContainer {
+    fn of(header: &ContainerHeader) -> Self {
+        Self {
+            endian: header.endian,
+            version: header.version,
+            size: header.size,
+            alignment: header.alignment,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NamedFiles {
+    /// Header of the container of a model, `None` for the files of a container of format 1, 3 or 4.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<SubContainer>,
+    #[serde(default)]
+    pub strings: StringTableFormat,
+    /// If the files of a model are one after the other, without the header of the block.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub headerless: bool,
+    pub files: Vec<NamedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TextureSet {
+    /// Header of the container of a set of format 2, `None` for the set of a container of format 6.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<SubContainer>,
+    #[serde(default)]
+    pub strings: StringTableFormat,
+    /// If the textures are one after the other, without the header of the block.
+    #[serde(default)]
+    pub headerless: bool,
+    pub textures: Vec<Texture>,
+}
+
+/// Archive made of a single block of textures, without a container. Older metadata.json have its `Textures` instead.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TextureBlock {
+    pub endian: Endian,
+    /// If the textures are one after the other, without the header of the block.
+    #[serde(default)]
+    pub headerless: bool,
+    pub textures: Vec<Texture>,
+}
+
+/// Name in the string table and path of the extracted file, which can differ if the name isn't usable as a filename.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NamedFile {
+    pub name: String,
+    pub filename: String,
+    /// Format of the message table, if the file is one extracted to CSV.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub messages: Option<MessageFormat>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Blob {
+    pub filename: String,
+    pub file_type: FileType,
+}
 
 pub struct CatFileReader {
-    pub input: File,
+    input: Cursor<SharedMap>,
     pub output: Option<String>,
+    /// Profile given on the command line (`--game` or `--platform`), otherwise it's detected.
+    given_profile: Option<Profile>,
+    profile: Profile,
+    /// Byte order of the container being unpacked.
+    endian: Endian,
+    /// Subdirectory of `output` of the container being unpacked.
+    path: Vec<String>,
+    /// Paths already extracted, lowercase because of case-insensitive filesystems.
+    used_paths: HashSet<String>,
+    /// Paths of the entries already read (`2/0/name`), lowercase too so they are numbered like their files.
+    used_entries: HashSet<String>,
+    /// Path of the entry of the last file claimed, given to the next node added.
+    entry_path: Option<String>,
+    /// Inconsistencies that don't prevent unpacking.
+    problems: Vec<String>,
+    /// Patterns given to `--only`, everything is extracted if `None`.
+    only: Option<GlobSet>,
+    /// Paths matching `only`.
+    selected_paths: HashSet<String>,
+    /// Nodes being read, each one is a child of the previous one.
+    nodes: Vec<Node>,
+    /// Structure of the archive, once it's unpacked.
+    tree: Option<Node>,
 }
 
 impl CatFileReader {
-    pub fn new(input: &str, output: Option<String>) -> Self {
-        Self {
-            input: File::open(&input).unwrap(),
-            output,
+    pub fn new(input: &str, output: Option<String>, profile: Option<Profile>) -> std::io::Result<Self> {
+        let file = File::open(input).map_err(|e| std::io::Error::new(e.kind(), format!("{input}: {e}")))?;
+        if file.metadata()?.len() == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("'{input}' is empty")));
         }
+        // the archive isn't supposed to be modified while it's read
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| std::io::Error::new(e.kind(), format!("{input}: {e}")))?;
+
+        Ok(Self {
+            input: Cursor::new(SharedMap(Rc::new(map))),
+            output,
+            given_profile: profile,
+            profile: Platform::Pc.profile(),
+            endian: Endian::Little,
+            path: vec![],
+            used_paths: HashSet::new(),
+            used_entries: HashSet::new(),
+            entry_path: None,
+            problems: vec![],
+            only: None,
+            selected_paths: HashSet::new(),
+            nodes: vec![],
+            tree: None,
+        })
     }
 
     pub fn unpack(&mut self) -> std::io::Result<ArchiveEntry> {
-        let value = self.peek_u32();
-        if value == 1 {
+        let mut magic = [0u8; 4];
+        self.input.read_exact(&mut magic)?;
+        self.input.seek(SeekFrom::Current(-4))?;
+
+        self.profile = match self.given_profile {
+            Some(profile) => profile,
+            None => Profile::detect(&magic),
+        };
+        self.endian = self.profile.endian;
+
+        if Endian::detect(&magic).is_some() {
             self.unpack_container()
         } else {
             self.unpack_gxt()
@@ -73,111 +260,96 @@ impl CatFileReader {
     }
 
     fn unpack_container(&mut self) -> std::io::Result<ArchiveEntry> {
-        let container_end = self.get_offset();
+        let parent_endian = self.endian;
+        let container_start = self.get_offset();
 
         let Ok(header) = self.read_header() else {
             let pos = self.get_offset();
             return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid header at position {:#X}", pos)));
         };
 
-        let container_end = container_end + (header.size + header.content_size) as u64;
+        let container_end = container_start + (header.size + header.content_size) as u64;
 
-        assert!(header.version < 3);
+        if header.version >= 3 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Unsupported version {} of the container at {:#X}", header.version, container_start)));
+        }
 
         let mut children = vec![];
 
         match header.format {
-            0 => match (header.size, header.alignment) {
-                (64, 64) | (32, 16) => {
-                    for (id, child) in header.children.iter().enumerate() {
-                        self.input.seek(SeekFrom::Start(child.offset))?;
-
-                        if let Some(output_dir) = &self.output {
-                            std::fs::create_dir_all(output_dir)?;
-                            self.extract_file(child, &format!("{output_dir}/{:#X}.bin", child.offset))?;
-                        } else {
-                            println!("{id}: {:?}", child);
-                        }
-
-                        children.push(ArchiveEntry::File(format!("{:#X}.bin", child.offset)));
-                    }
-                },
-                _ => {
-                    for (id, child) in header.children.iter().enumerate() {
-                        self.input.seek(SeekFrom::Start(child.offset))?;
-                        let val = self.peek_u32();
-                        if val == 1 {
-                            children.push(self.unpack_container()?);
-                        } else {
-                            if let Some(output_dir) = &self.output {
-                                std::fs::create_dir_all(output_dir)?;
-                                self.extract_file(child, &format!("{output_dir}/{:#X}.bin", child.offset))?;
-                            } else {
-                                println!("{id}: {:#X} [{}, {}, {}, {}]", child.offset,
-                                    self.input.read_u32::<LittleEndian>()?,
-                                    self.input.read_u32::<LittleEndian>()?,
-                                    self.input.read_u32::<LittleEndian>()?,
-                                    self.input.read_u32::<LittleEndian>()?);
-                            }
-
-                            children.push(ArchiveEntry::File(format!("{:#X}.bin", child.offset)));
-                        }
-                    }
+            0 => {
+                for (id, child) in header.children.iter().enumerate() {
+                    children.push(self.unpack_file(id, child)?);
                 }
             },
             1 | 3 | 4 => {
                 children.push(self.unpack_format_1(&header)?);
             },
             2 => {
-                for child in header.children.iter() {
-                    children.push(self.unpack_format_2(child)?);
+                for (id, child) in header.children.iter().enumerate() {
+                    self.path.push(id.to_string());
+                    let child = self.unpack_format_2(child);
+                    self.path.pop();
+                    children.push(child?);
                 }
             },
             5 => {
+                // 1 or 2: scripts, 3: names then 2 navmesh, 4: names then scripts
+                let names = if header.children.len() > 2 {
+                    let strings = &header.children[0];
+                    Some(self.read_strings(strings.offset, strings.size).names)
+                } else {
+                    None
+                };
+
                 for (id, child) in header.children.iter().enumerate() {
-                    let offset = self.get_offset();
-                    if let Some(output_dir) = &self.output {
-                        std::fs::create_dir_all(output_dir)?;
-                        self.extract_file(child, &format!("{output_dir}/{:#X}.bin", offset))?;
+                    let name = match &names {
+                        Some(_) if id == 0 => {
+                            // its node was added when reading the names, and string tables aren't entries
+                            children.push(ArchiveEntry::File(self.extract_file(child, &id.to_string(), &format!("{id}.bin"))?));
+                            self.entry_path = None;
+                            continue;
+                        },
+                        Some(names) => names.get(id - 1).cloned(),
+                        None => None,
+                    };
+
+                    if header.children.len() == 3 {
+                        children.push(self.unpack_navmesh(id, child, name)?);
                     } else {
-                        println!("{id}: {:?}", child);
+                        children.push(self.unpack_script(id, child, name)?);
                     }
                 }
             },
             6 =>{
-                assert_eq!(header.children.len(), 2);
+                check_children(&header, container_start, 2)?;
 
                 children.push(self.unpack_format_6(&header)?);
             },
             8 => {
-                for child in header.children.iter() {
+                for (id, child) in header.children.iter().enumerate() {
                     self.input.seek(SeekFrom::Start(child.offset))?;
-                    children.push(self.unpack_format_8()?);
+                    self.path.push(id.to_string());
+                    let child = self.unpack_format_8();
+                    self.path.pop();
+                    children.push(child?);
                 }
             },
             _ => {
-                println!("Unknown format {}.", header.format);
+                eprintln!("Unknown format {}.", header.format);
+                self.problems.push(format!("Unknown format {} at {:#X}", header.format, container_start));
                 for (id, child) in header.children.iter().enumerate() {
-                    self.input.seek(SeekFrom::Start(child.offset))?;
-                    if let Some(output_dir) = &self.output {
-                        std::fs::create_dir_all(output_dir)?;
-                        self.extract_file(child, &format!("{output_dir}/{:#X}.bin", child.offset))?;
-                    } else {
-                        println!("{id}: {:#X} [{}, {}, {}, {}]", child.offset,
-                            self.input.read_u32::<LittleEndian>()?,
-                            self.input.read_u32::<LittleEndian>()?,
-                            self.input.read_u32::<LittleEndian>()?,
-                            self.input.read_u32::<LittleEndian>()?);
-                    }
-
-                    children.push(ArchiveEntry::File(format!("{:#X}.bin", child.offset)));
+                    children.push(self.unpack_file(id, child)?);
                 }
             }
         };
 
         self.input.seek(SeekFrom::Start(container_end))?;
+        self.endian = parent_endian;
+        self.close_node();
 
         Ok(ArchiveEntry::Container(Container {
+            endian: header.endian,
             version: header.version,
             format: header.format,
             size: header.size,
@@ -187,22 +359,29 @@ impl CatFileReader {
     }
 
     fn unpack_gxt(&mut self) -> std::io::Result<ArchiveEntry> {
-        let images_data = self.unpack_block(0)?;
+        let file_size = self.input.get_ref().as_ref().len() as u64;
+        let headerless = self.is_headerless_block(0)?;
+        self.open_node(Node::new(None, 0, file_size, NodeKind::Block { headerless }));
+        let images_data = self.unpack_block(0, file_size)?;
         let mut textures = vec![];
+        let mut images = vec![];
 
         for (id, image_data) in images_data.iter().enumerate() {
-            let mut buffer = vec![0u8; image_data.size as usize];
-            self.input.read(&mut buffer)?;
-            let dds = Dds::read(&*buffer).unwrap();
-            let image = image_from_dds(&dds, 0).unwrap();
-            let filename = format!("{:#X}.png", image_data.offset);
-            let format = dds.get_texture_format();
-
-            if let Some(output_dir) = &self.output {
-                std::fs::create_dir_all(output_dir)?;
-                image.save(&format!("{output_dir}/{filename}")).unwrap();
-            } else {
-                println!("{id}: {filename} {:?}", image_data);
+            let map = self.map();
+            let buffer = map.slice(image_data.offset, image_data.size)?;
+
+            if FileType::detect(buffer) != FileType::Dds {
+                textures.push(self.unpack_raw_texture(None, &id.to_string(), buffer, image_data)?);
+                continue;
+            }
+
+            let dds = read_dds(buffer, image_data.offset)?;
+            let filename = self.claim_path(&id.to_string(), &format!("{id}.png"));
+            let format = texture_format(&dds, image_data.offset)?;
+            self.add_node(None, image_data, texture_node(FileType::Dds, Some((dds.get_width(), dds.get_height())), Some(format.clone())));
+
+            if let Some(path) = self.output_path(&filename)? {
+                images.push((dds, path));
             }
 
             textures.push(Texture {
@@ -212,28 +391,65 @@ impl CatFileReader {
             });
         }
 
-        Ok(ArchiveEntry::Textures(textures))
+        decode_textures(images)?;
+        self.close_node();
+
+        Ok(ArchiveEntry::TextureBlock(TextureBlock {
+            endian: self.endian,
+            headerless,
+            textures,
+        }))
     }
 
     fn unpack_format_1(&mut self, header: &ContainerHeader) -> std::io::Result<ArchiveEntry> {
-        let strings = &header.children[0];
+        let Some((strings, children)) = header.children.split_first() else {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Container of files without names"));
+        };
+        let offset = strings.offset;
         let strings = self.read_strings(strings.offset, strings.size);
+        if strings.names.len() != children.len() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} names for {} files at position {:#X}", strings.names.len(), children.len(), offset)));
+        }
 
         let mut files = vec![];
-        for (id, child) in header.children.iter().skip(1).enumerate() {
-            let filename = strings[id].clone();
-
-            if let Some(output_dir) = &self.output {
-                std::fs::create_dir_all(output_dir)?;
-                self.extract_file(child, &format!("{output_dir}/{filename}"))?;
-            } else {
-                println!("{id}: {filename} {:?}", child);
+        for (child, name) in children.iter().zip(strings.names.iter().cloned()) {
+            let map = self.map();
+            let buffer = map.slice(child.offset, child.size)?;
+            let file_type = FileType::detect(buffer);
+
+            // message tables are converted to CSV, like in containers of format 0
+            let table = match file_type {
+                FileType::Unknown => MessageTable::read(buffer, self.endian),
+                _ => None,
+            };
+            if let Some(table) = table {
+                let filename = self.claim_path(&name, &format!("{name}.csv"));
+                self.write_output(&filename, &table.to_csv()?)?;
+                self.add_node(Some(&name), child, NodeKind::Messages { count: table.messages.len() });
+
+                files.push(NamedFile {
+                    name,
+                    filename,
+                    messages: Some(table.format),
+                });
+                continue;
             }
 
-            files.push(filename);
+            let filename = self.extract_file(child, &name, &name)?;
+            self.add_node(Some(&name), child, NodeKind::File { file_type, words: None });
+            files.push(NamedFile {
+                filename,
+                name,
+                messages: None,
+            });
         }
 
-        Ok(ArchiveEntry::Files(files))
+        Ok(ArchiveEntry::NamedFiles(NamedFiles {
+            container: None,
+            strings: strings.format,
+            headerless: false,
+            files,
+        }))
     }
 
     fn unpack_format_2(&mut self, data: &ChildData) -> std::io::Result<ArchiveEntry> {
@@ -244,10 +460,18 @@ impl CatFileReader {
             return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid header at position {:#X}", pos)));
         };
 
-        assert_eq!(header.format, 0);
-        assert_eq!(header.children.len(), 2);
+        check_format(&header, data.offset, 0)?;
+        check_children(&header, data.offset, 2)?;
         
-        self.unpack_strings_then_images(&header)
+        let set = self.unpack_strings_then_images(&header).map(|set| match set {
+            ArchiveEntry::TextureSet(set) => ArchiveEntry::TextureSet(TextureSet {
+                container: Some(SubContainer::of(&header)),
+                ..set
+            }),
+            set => set,
+        });
+        self.close_node();
+        set
     }
 
     fn unpack_format_6(&mut self, header: &ContainerHeader) -> std::io::Result<ArchiveEntry> {
@@ -262,120 +486,319 @@ impl CatFileReader {
 
         self.input.seek(SeekFrom::Start(images.offset))?;
 
-        let mut textures = vec![];
+        let headerless = self.is_headerless_block(images.offset)?;
+        self.open_node(Node::new(None, images.offset, images.size, NodeKind::Block { headerless }));
 
-        let magic = self.peek_u32_be();
-        match magic {
-            GNF => {
-                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("GNF Textures are not supported")));
-            },
-            _ => {
-                let images_data = self.unpack_block(images.offset)?;
-                for (id, name) in strings.iter().enumerate() {
-                    let image = &images_data[id];
-                    let mut buffer = vec![0u8; image.size as usize];
-                    self.input.read(&mut buffer)?;
-
-                    // some modders put PNG instead of DDS
-                    if &buffer[0..4] == [0x89, 0x50, 0x4E, 0x47] {
-                        let filename = format!("{name} ({:#X}).png", self.get_offset());
-
-                        if let Some(output_dir) = &self.output {
-                            std::fs::create_dir_all(output_dir)?;
-                            std::fs::write(format!("{output_dir}/{filename}"), buffer)?;
-                        }
-
-                        textures.push(Texture {
-                            name: name.clone(),
-                            format: TextureFormat::D3DFormat(Compression::Dxt5, PixelFormat::BC3RgbaUnorm),
-                            filename,
-                        });
-                        continue;
-                    } else if &buffer[0..4] != [0x44, 0x44, 0x53, 0x20] {
-                        panic!("{:?}", &buffer[0..4]);
-                    }
+        let images_data = self.unpack_block(images.offset, images.size)?;
+        if images_data.len() != strings.names.len() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} names for {} textures at position {:#X}", strings.names.len(), images_data.len(), images.offset)));
+        }
 
-                    let dds = Dds::read(&*buffer).unwrap();
-                    let image = image_from_dds(&dds, 0).unwrap();
-                    let filename = format!("{name} ({}x{}).png", image.width(), image.height());
-                    let format = dds.get_texture_format();
+        let mut textures = vec![];
+        let mut images = vec![];
+
+        for (id, name) in strings.names.iter().enumerate() {
+            let image = &images_data[id];
+            let map = self.map();
+            let buffer = map.slice(image.offset, image.size)?;
+
+            // some modders put PNG instead of DDS
+            if buffer.starts_with(&[0x89, 0x50, 0x4E, 0x47]) {
+                let filename = self.claim_path(name, &format!("{name}.png"));
+                self.write_output(&filename, buffer)?;
+                self.add_node(Some(name), image, texture_node(FileType::Png, png_size(buffer), None));
+
+                textures.push(Texture {
+                    name: name.clone(),
+                    format: TextureFormat::D3DFormat(Compression::Dxt5, PixelFormat::BC3RgbaUnorm),
+                    filename,
+                });
+                continue;
+            } else if !buffer.starts_with(b"DDS ") {
+                textures.push(self.unpack_raw_texture(Some(name), name, buffer, image)?);
+                continue;
+            }
 
-                    if let Some(output_dir) = &self.output {
-                        std::fs::create_dir_all(output_dir)?;
-                        image.save(&format!("{output_dir}/{filename}")).unwrap();
-                    } else {
-                        println!("{id}: {filename} {:?}", images_data[id]);
-                    }
+            let dds = read_dds(buffer, image.offset)?;
+            let filename = self.claim_path(name, &format!("{name} ({}x{}).png", dds.get_width(), dds.get_height()));
+            let format = texture_format(&dds, image.offset)?;
+            self.add_node(Some(name), image, texture_node(FileType::Dds, Some((dds.get_width(), dds.get_height())), Some(format.clone())));
 
-                    textures.push(Texture {
-                        name: name.clone(),
-                        format,
-                        filename,
-                    });
-                }
+            if let Some(path) = self.output_path(&filename)? {
+                images.push((dds, path));
             }
+
+            textures.push(Texture {
+                name: name.clone(),
+                format,
+                filename,
+            });
         }
 
-        Ok(ArchiveEntry::Textures(textures))
+        decode_textures(images)?;
+        self.close_node();
+
+        Ok(ArchiveEntry::TextureSet(TextureSet {
+            container: None,
+            strings: strings.format,
+            headerless,
+            textures,
+        }))
+    }
+
+    /// Textures that can't be converted are extracted as they are.
+    fn unpack_raw_texture(&mut self, name: Option<&str>, stem: &str, buffer: &[u8], data: &ChildData) -> std::io::Result<Texture> {
+        let file_type = FileType::detect(buffer);
+        let filename = self.claim_path(stem, &format!("{stem}.{}", file_type.extension()));
+        self.write_output(&filename, buffer)?;
+        self.add_node(name, data, texture_node(file_type, None, None));
+
+        Ok(Texture {
+            name: name.unwrap_or_default().to_string(),
+            format: TextureFormat::Raw(file_type),
+            filename,
+        })
     }
 
     fn unpack_format_8(&mut self) -> std::io::Result<ArchiveEntry> {
+        let header_addr = self.get_offset();
         let Ok(header) = self.read_header() else {
             let pos = self.get_offset();
             return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid header at position {:#X}", pos)));
         };
 
-        assert_eq!(header.format, 0);
-        assert_eq!(header.children.len(), 2);
+        check_format(&header, header_addr, 0)?;
+        check_children(&header, header_addr, 2)?;
 
         let strings = &header.children[0];
         let files = &header.children[1];
 
-        //let strings = self.read_strings(strings.offset, strings.size);
+        // mtl.csv and <model>.bin
+        let strings = self.read_strings(strings.offset, strings.size);
+        let names = &strings.names;
+        let files_data = match self.unpack_block(files.offset, files.size) {
+            Ok(files_data) if files_data.len() == names.len() => files_data,
+            _ => {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid model block at position {:#X}", files.offset)));
+            },
+        };
+
         let mut children = vec![];
+        let headerless = self.is_headerless_block(files.offset)?;
+        self.open_node(Node::new(None, files.offset, files.size, NodeKind::Block { headerless }));
+        for (name, data) in names.iter().zip(files_data.iter()) {
+            let map = self.map();
+            let buffer = map.slice(data.offset, data.size)?;
+            let materials = match name.ends_with(".csv").then(|| MaterialTable::parse(buffer)) {
+                Some(Ok(table)) => Some(table.materials),
+                Some(Err(e)) => {
+                    // packed back as it is
+                    self.problems.push(format!("{name} at {:#X} isn't a material table: {e}", data.offset));
+                    None
+                },
+                None => None,
+            };
+            let kind = match materials {
+                Some(materials) => NodeKind::Materials { materials },
+                None => NodeKind::File { file_type: FileType::detect(buffer), words: None },
+            };
+            let filename = self.extract_file(data, name, name)?;
+            self.add_node(Some(name), data, kind);
+
+            children.push(NamedFile {
+                filename,
+                name: name.clone(),
+                messages: None,
+            });
+        }
 
-        if let Some(output_dir) = &self.output.clone() {
-            std::fs::create_dir_all(output_dir)?;
-            self.extract_file(strings, &format!("{output_dir}/{:#X}.bin", strings.offset))?;
-            self.extract_file(files, &format!("{output_dir}/{:#X}.bin", files.offset))?;
+        // the block, then the container
+        self.close_node();
+        self.close_node();
 
-            children.push(format!("{:#X}.bin", strings.offset));
-            children.push(format!("{:#X}.bin", files.offset));
-        } else {
-            println!("0: Unknown format");
+        Ok(ArchiveEntry::NamedFiles(NamedFiles {
+            container: Some(SubContainer::of(&header)),
+            strings: strings.format,
+            headerless,
+            files: children,
+        }))
+    }
+
+    /// Extracts a child of unknown content: nested containers are unpacked, message tables are converted to CSV.
+    fn unpack_file(&mut self, id: usize, data: &ChildData) -> std::io::Result<ArchiveEntry> {
+        let map = self.map();
+        let buffer = map.slice(data.offset, data.size)?;
+
+        let file_type = FileType::detect(buffer);
+        if file_type == FileType::Container {
+            let endian = Endian::detect(buffer).unwrap();
+            let size = match endian.u32_at(&buffer[12..]) {
+                0 => 256,
+                size => size as u64,
+            };
+            let content_size = endian.u32_at(&buffer[16..]) as u64;
+            if size + content_size <= data.size {
+                self.input.seek(SeekFrom::Start(data.offset))?;
+                self.path.push(id.to_string());
+                let container = self.unpack_container();
+                self.path.pop();
+                return container;
+            }
+        }
+        if file_type == FileType::Unknown {
+            if let Some(table) = MessageTable::read(buffer, self.endian) {
+                let filename = self.claim_path(&id.to_string(), &format!("{id}.csv"));
+                self.write_output(&filename, &table.to_csv()?)?;
+                self.add_node(None, data, NodeKind::Messages { count: table.messages.len() });
+
+                return Ok(ArchiveEntry::Messages(Messages {
+                    filename,
+                    format: table.format,
+                }));
+            }
         }
 
-        Ok(ArchiveEntry::Files(children))
+        let filename = self.claim_path(&id.to_string(), &format!("{id}.{}", file_type.extension()));
+        self.write_output(&filename, buffer)?;
+        let kind = match (file_type, A001File::read(buffer, self.endian)) {
+            (FileType::A001, Some(a001)) => NodeKind::A001 {
+                header: a001.header,
+                sections: a001.sections,
+            },
+            (FileType::Unknown, _) => {
+                let mut words = [0u8; 16];
+                let length = buffer.len().min(16);
+                words[..length].copy_from_slice(&buffer[..length]);
+                NodeKind::File {
+                    file_type,
+                    words: Some([0, 4, 8, 12].map(|start| self.endian.u32_at(&words[start..]))),
+                }
+            },
+            _ => NodeKind::File { file_type, words: None },
+        };
+        self.add_node(None, data, kind);
+
+        Ok(ArchiveEntry::Blob(Blob {
+            filename,
+            file_type,
+        }))
+    }
+
+    /// `name` comes from the string table of the container, if it has one.
+    fn unpack_navmesh(&mut self, id: usize, data: &ChildData, name: Option<String>) -> std::io::Result<ArchiveEntry> {
+        let map = self.map();
+        let buffer = map.slice(data.offset, data.size)?;
+        let stem = name.clone().unwrap_or_else(|| id.to_string());
+
+        let Ok(navmesh) = Navmesh::read(buffer, self.endian) else {
+            let filename = self.claim_path(&stem, &format!("{stem}.bin"));
+            self.write_output(&filename, buffer)?;
+            self.add_node(name.as_deref(), data, NodeKind::File { file_type: FileType::detect(buffer), words: None });
+            return Ok(ArchiveEntry::File(filename));
+        };
+
+        let filename = self.claim_path(&stem, &format!("{stem}.obj"));
+        if let Some(path) = self.output_path(&filename)? {
+            let mut writer = BufWriter::new(File::create(path)?);
+            navmesh.to_obj(&mut writer)?;
+        }
+        self.add_node(name.as_deref(), data, NodeKind::Navmesh {
+            vertices: navmesh.vertices.len(),
+            polygons: navmesh.polygons.len(),
+        });
+
+        Ok(ArchiveEntry::Navmesh(filename))
     }
 
-    fn unpack_block(&mut self, offset: u64) -> std::io::Result<Vec<ChildData>> {
+    /// `name` comes from the string table of the container, if it has one.
+    fn unpack_script(&mut self, id: usize, data: &ChildData, name: Option<String>) -> std::io::Result<ArchiveEntry> {
+        let map = self.map();
+        let buffer = map.slice(data.offset, data.size)?;
+        let stem = name.clone().unwrap_or_else(|| id.to_string());
+
+        let items = script::read(buffer, self.endian);
+        if script::write(&items, self.endian) != buffer {
+            let filename = self.claim_path(&stem, &format!("{stem}.bin"));
+            self.write_output(&filename, buffer)?;
+            self.add_node(name.as_deref(), data, NodeKind::File { file_type: FileType::detect(buffer), words: None });
+            return Ok(ArchiveEntry::File(filename));
+        }
+
+        let filename = self.claim_path(&stem, &format!("{stem}.txt"));
+        self.write_output(&filename, script::to_text(&items).as_bytes())?;
+        let strings = items.iter().filter_map(|item| match item {
+            ScriptItem::String(string) => Some(string.clone()),
+            _ => None,
+        }).collect();
+        self.add_node(name.as_deref(), data, NodeKind::Script { strings });
+
+        Ok(ArchiveEntry::Script(filename))
+    }
+
+    fn unpack_block(&mut self, offset: u64, size: u64) -> std::io::Result<Vec<ChildData>> {
         let mut children_data: Vec<ChildData> = vec![];
 
         let mut block_start = self.input.seek(SeekFrom::Start(offset))?;
 
-        // arbitrary size
-        if self.peek_u32() > 0xFFFF {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Block without a header is not supported, at pos {:#X}.", self.get_offset())));
+        if self.is_headerless_block(offset)? {
+            return self.unpack_headerless_block(offset, size);
         }
 
-        let block_header_size = self.input.read_u32::<LittleEndian>()?;
-        let children_count = self.input.read_u32::<LittleEndian>()?;
-        let block_size = self.input.read_u32::<LittleEndian>()?;
+        let block_header_size = self.read_u32()?;
+        let children_count = self.read_u32()?;
+        let block_size = self.read_u32()?;
         block_start += block_header_size as u64;
         for _ in 0..children_count {
             children_data.push(ChildData {
-                offset: self.input.read_u32::<LittleEndian>()? as u64 + block_start,
+                offset: self.read_u32()? as u64 + block_start,
                 size: 0,
             });
         }
 
-        for i in 0..(children_count - 1) {
-            let k = i as usize;
-            children_data[k].size = children_data[k + 1].offset - children_data[k].offset;
+        // the size of the block includes its header, and the block can't go past its parent
+        let block_end = (offset + block_size as u64).min(offset + size);
+        let ends: Vec<_> = children_data.iter().skip(1).map(|child| child.offset).chain(std::iter::once(block_end)).collect();
+        for (child, end) in children_data.iter_mut().zip(ends) {
+            child.size = end.checked_sub(child.offset).ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, format!("The files of the block at {:#X} aren't in order", offset))
+            })?;
+        }
+
+        Ok(children_data)
+    }
+
+    fn is_headerless_block(&mut self, offset: u64) -> std::io::Result<bool> {
+        self.input.seek(SeekFrom::Start(offset))?;
+
+        // arbitrary size
+        Ok(self.peek_u32()? > 0xFFFF)
+    }
+
+    /// The files (DDS or PNG) are one after the other, their sizes come from their headers.
+    /// A file of another type (GNF, GXT) takes the rest of the block.
+    fn unpack_headerless_block(&mut self, offset: u64, size: u64) -> std::io::Result<Vec<ChildData>> {
+        let map = self.map();
+        let buffer = map.slice(offset, size)?;
+
+        let mut children_data = vec![];
+        let mut pos = 0;
+        while pos < buffer.len() && buffer[pos..].iter().any(|b| *b != 0) {
+            let size = match texture::texture_size(&buffer[pos..]) {
+                Some(size) => size,
+                None if TextureCodec::from_file_type(FileType::detect(&buffer[pos..])).is_some() => buffer.len() - pos,
+                None => {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Unknown file in block without a header, at pos {:#X}.", offset + pos as u64)));
+                },
+            };
+
+            children_data.push(ChildData {
+                offset: offset + pos as u64,
+                size: size as u64,
+            });
+            pos += size;
         }
-        let last_child = children_data.last_mut().unwrap();
-        last_child.size = (block_start + block_size as u64) - last_child.offset;
 
+        self.input.seek(SeekFrom::Start(offset))?;
         Ok(children_data)
     }
 
@@ -384,33 +807,50 @@ impl CatFileReader {
 
         let header_addr = self.get_offset();
         
-        // part one
-        let val = self.input.read_u32::<LittleEndian>()?; assert_eq!(val, 1);
-        let version = self.input.read_u32::<LittleEndian>()?;
-        let val = self.input.read_u32::<LittleEndian>()?; assert_eq!(val, 0);
-        let mut size = self.input.read_u32::<LittleEndian>()?;
+        // part one, the first word (1) gives the byte order
+        let mut magic = [0u8; 4];
+        self.input.read_exact(&mut magic)?;
+        let Some(endian) = Endian::detect(&magic) else {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Not a container at position {:#X}", header_addr)));
+        };
+        self.endian = endian;
+
+        let version = self.read_u32()?;
+        let val = self.read_u32()?;
+        if val != 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Not a container at position {:#X}", header_addr)));
+        }
+        let mut size = self.read_u32()?;
         if size == 0 {
-            println!("[DEBUG] read_header: size is 0.");
+            eprintln!("[DEBUG] read_header: size is 0.");
             size = 256;
         }
-        assert!(size >= 32);
+        if size < 32 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("The header of the container at {:#X} is {} bytes, too small", header_addr, size)));
+        }
 
-        let content_size = self.input.read_u32::<LittleEndian>()?;
+        let content_size = self.read_u32()?;
 
         // maybe check the duplicate values?
         let byte_zero = self.input.seek(SeekFrom::Current(size as i64 - 20))?;
 
         // part two
-        let val = self.input.read_u32::<LittleEndian>()?; assert_eq!(val, 0);
-        let child_count = self.input.read_u32::<LittleEndian>()? as usize;
-        let format = self.input.read_u32::<LittleEndian>()?;
-        let alignment = self.input.read_u32::<LittleEndian>()?;
-        let val = self.input.read_u32::<LittleEndian>()?; assert_eq!(val, 0);
+        let val = self.read_u32()?;
+        if val != 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid byte zero of the container at {:#X}", header_addr)));
+        }
+        let child_count = self.read_u32()? as usize;
+        let format = self.read_u32()?;
+        let alignment = self.read_u32()?;
+        let val = self.read_u32()?;
+        if val != 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid byte zero of the container at {:#X}", header_addr)));
+        }
 
         let mut data = vec![];
         for _ in 0..child_count {
-            data.push(self.input.read_u32::<LittleEndian>()?);
-            data.push(self.input.read_u32::<LittleEndian>()?);
+            data.push(self.read_u32()?);
+            data.push(self.read_u32()?);
         }
 
         self.align(alignment);
@@ -423,7 +863,18 @@ impl CatFileReader {
             });
         }
 
+        let end_of_container = header_addr + size as u64 + content_size as u64;
+        if end_of_container > self.input.get_ref().as_ref().len() as u64 {
+            self.problems.push(format!("The container at {:#X} ends at {:#X}, after the end of the file", header_addr, end_of_container));
+        }
+        for (id, child) in children.iter().enumerate() {
+            if child.offset < byte_zero || child.offset + child.size > end_of_container {
+                self.problems.push(format!("Child {id} of the container at {:#X} is outside of it ({:#X}, {} bytes)", header_addr, child.offset, child.size));
+            }
+        }
+
         let header = ContainerHeader {
+            endian,
             version,
             format,
             size,
@@ -431,34 +882,91 @@ impl CatFileReader {
             alignment,
             children,
         };
-        if self.output.is_none() {
-            println!("{:#X}: {:?}", header_addr, header);
-        }
+        self.open_node(Node::new(None, header_addr, size as u64 + content_size as u64, NodeKind::Container {
+            version,
+            format,
+            alignment,
+            endian,
+            count: header.children.len(),
+        }));
 
         Ok(header)
     }
 
     fn get_offset(&mut self) -> u64 {
-        self.input.seek(SeekFrom::Current(0)).unwrap()
+        self.input.stream_position().unwrap()
+    }
+
+    /// `size` bytes of the archive at `offset`.
+    pub fn bytes(&self, offset: u64, size: u64) -> std::io::Result<&[u8]> {
+        self.input.get_ref().slice(offset, size)
+    }
+
+    /// Structure of the archive, once it's unpacked.
+    pub fn tree(&self) -> Option<&Node> {
+        self.tree.as_ref()
+    }
+
+    /// Starts a node, the next ones are its children until it's closed.
+    fn open_node(&mut self, node: Node) {
+        self.nodes.push(node);
+    }
+
+    fn close_node(&mut self) {
+        let Some(mut node) = self.nodes.pop() else {
+            return;
+        };
+
+        match self.nodes.last_mut() {
+            Some(parent) => {
+                node.id = parent.children.len();
+                parent.children.push(node);
+            },
+            None => self.tree = Some(node),
+        }
+    }
+
+    fn add_node(&mut self, name: Option<&str>, data: &ChildData, kind: NodeKind) {
+        let mut node = Node::new(name.map(str::to_string), data.offset, data.size, kind);
+        node.path = self.entry_path.take();
+        self.open_node(node);
+        self.close_node();
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    pub fn problems(&self) -> &[String] {
+        &self.problems
+    }
+
+    /// The archive, to borrow children while the reader is used.
+    fn map(&self) -> SharedMap {
+        self.input.get_ref().clone()
     }
 
     fn align(&mut self, alignment: u32) {
         if alignment > 0 {
             let alignment = alignment as u64;
-            let cur_pos = self.input.seek(SeekFrom::Current(0)).unwrap();
-            if (cur_pos % alignment) != 0 {
+            let cur_pos = self.input.stream_position().unwrap();
+            if !cur_pos.is_multiple_of(alignment) {
                 let cur_pos = (alignment - (cur_pos % alignment)) as i64;
                 self.input.seek(SeekFrom::Current(cur_pos)).unwrap();
             }
         } else {
-            println!("[DEBUG] Alignement is NULL");
+            eprintln!("[DEBUG] Alignement is NULL");
         }
     }
 
-    fn peek_u32(&mut self) -> u32 {
-        let val = self.input.read_u32::<LittleEndian>().unwrap();
-        self.input.seek(SeekFrom::Current(-4)).unwrap();
-        val
+    fn read_u32(&mut self) -> std::io::Result<u32> {
+        self.endian.read_u32(&mut self.input)
+    }
+
+    fn peek_u32(&mut self) -> std::io::Result<u32> {
+        let val = self.read_u32()?;
+        self.input.seek(SeekFrom::Current(-4))?;
+        Ok(val)
     }
 
     #[allow(unused)]
@@ -468,41 +976,162 @@ impl CatFileReader {
         val
     }
 
-    fn extract_file(&mut self, data: &ChildData, filename: &str) -> std::io::Result<()> {
-        self.input.seek(SeekFrom::Start(data.offset)).unwrap();
-        let mut buffer = vec![0u8; data.size as usize];
-        self.input.read(&mut buffer)?;
+    /// Extracts `data` to `filename` in the current directory, returns the path written in metadata.json.
+    fn extract_file(&mut self, data: &ChildData, name: &str, filename: &str) -> std::io::Result<String> {
+        let filename = self.claim_path(name, filename);
+        if self.output.is_some() {
+            let map = self.map();
+            let buffer = map.slice(data.offset, data.size)?;
+
+            self.write_output(&filename, buffer)?;
+        }
+
+        Ok(filename)
+    }
+
+    /// Path (relative to the root of the extraction) where to extract `filename` in the current directory.
+    /// The name is sanitized, and a number is added if it's already used.
+    ///
+    /// `name` is the name of the entry in the string table (or its index). It gets the same number as the file,
+    /// and gives the path of the entry (`2/0/name (2)`) used by `--only`, `replace`, `remove`, `diff` and `mkpatch`.
+    fn claim_path(&mut self, name: &str, filename: &str) -> String {
+        let filename = sanitize_filename(filename);
+        let join = |reader: &Self, name: &str| reader.path.iter().map(String::as_str).chain(std::iter::once(name)).collect::<Vec<_>>().join("/");
+
+        let (mut path, mut entry_name) = (join(self, &filename), name.to_string());
+        let mut duplicate = 2;
+        while self.used_paths.contains(&path.to_lowercase()) || self.used_entries.contains(&join(self, &entry_name).to_lowercase()) {
+            path = join(self, &numbered(&filename, &filename, duplicate));
+            entry_name = numbered(name, &filename, duplicate);
+            duplicate += 1;
+        }
+        self.used_paths.insert(path.to_lowercase());
+        self.used_entries.insert(join(self, &entry_name).to_lowercase());
+        self.entry_path = Some(join(self, &entry_name));
+
+        if self.is_selected(&entry_name) {
+            self.selected_paths.insert(path.clone());
+        }
+
+        path
+    }
+
+    /// If the entry `name` in the current directory matches `--only`: its name, its path (`2/0/name`) or the path of one of its containers (`2/0`).
+    fn is_selected(&self, name: &str) -> bool {
+        let Some(only) = &self.only else {
+            return true;
+        };
 
-        std::fs::write(filename, buffer)?;
+        let entry_path = self.path.iter().map(String::as_str).chain(std::iter::once(name)).collect::<Vec<_>>();
+        only.is_match(name) || (1..=entry_path.len()).any(|length| only.is_match(entry_path[..length].join("/")))
+    }
 
+    /// Only extracts the entries matching one of `patterns` (names, paths or glob patterns), metadata.json can't be written then.
+    pub fn select(&mut self, patterns: &[String]) -> std::io::Result<()> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob = GlobBuilder::new(pattern).literal_separator(true).build()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid pattern '{pattern}': {e}")))?;
+            builder.add(glob);
+        }
+
+        let only = builder.build().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+        self.only = Some(only);
         Ok(())
     }
 
-    fn read_strings(&mut self, offset: u64, size: u64) -> Vec<String> {
-        self.input.seek(SeekFrom::Start(offset)).unwrap();
-        let mut buffer = vec![0u8; size as usize];
-        self.input.read(&mut buffer).unwrap();
-        let Ok(strings) = String::from_utf8(buffer.clone()) else {
-            eprintln!("Not a string buffer at offset {:#X}", offset);
-            return vec![];
+    /// Paths of the files extracted when only some entries are.
+    pub fn selected_paths(&self) -> &HashSet<String> {
+        &self.selected_paths
+    }
+
+    /// Full path of `path` (its directory is created), `None` if not extracting or if it isn't selected.
+    fn output_path(&self, path: &str) -> std::io::Result<Option<PathBuf>> {
+        let Some(output_dir) = &self.output else {
+            return Ok(None);
         };
-        let filenames: Vec<_> = strings.split("\n").map(|e| e.trim().trim_end_matches(',').to_string()).filter(|e| !e.is_empty()).collect();
+        if self.only.is_some() && !self.selected_paths.contains(path) {
+            return Ok(None);
+        }
+
+        let path = Path::new(output_dir).join(check_relative_path(path)?);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+
+        Ok(Some(path))
+    }
+
+    /// Writes `buffer` to `path` if extracting.
+    fn write_output(&self, path: &str, buffer: &[u8]) -> std::io::Result<()> {
+        if let Some(path) = self.output_path(path)? {
+            std::fs::write(path, buffer)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_strings(&mut self, offset: u64, size: u64) -> StringTable {
+        let map = self.map();
+        let buffer = map.slice(offset, size).unwrap_or_default();
+
+        let strings = StringTable::read(buffer).unwrap_or_else(|| {
+            eprintln!("Not a string buffer at offset {:#X}", offset);
+            StringTable {
+                names: vec![],
+                format: StringTableFormat::default(),
+            }
+        });
+        self.add_node(None, &ChildData { offset, size }, NodeKind::Names {
+            count: strings.names.len(),
+            encoding: strings.format.encoding,
+        });
 
-        filenames
+        strings
     }
 }
 
-pub trait SeekWrite: Write + Seek {}
-impl SeekWrite for std::fs::File {}
+/// Archive mapped in memory, children are slices of it instead of copies.
+#[derive(Clone)]
+struct SharedMap(Rc<Mmap>);
+
+impl AsRef<[u8]> for SharedMap {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl SharedMap {
+    fn slice(&self, offset: u64, size: u64) -> std::io::Result<&[u8]> {
+        let end = offset.checked_add(size).filter(|end| *end <= self.0.len() as u64);
+        match end {
+            Some(end) => Ok(&self.0[offset as usize..end as usize]),
+            None => Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, format!("{size} bytes at {offset:#X} are outside of the file"))),
+        }
+    }
+}
 
 pub struct CatFileWriter {
     pub input: ArchiveEntry,
-    pub output: File,
     root: String,
+    profile: Profile,
+}
+
+/// Child of a container being planned, before its position is known.
+enum Part<'a> {
+    Bytes(Vec<u8>),
+    /// File of the extracted directory, copied as it is.
+    File(String),
+    Container(&'a Container),
+    /// Container of format 0 that isn't in metadata.json (texture sets of format 2, models of format 8).
+    SubContainer(SubContainer, Vec<Part<'a>>),
+    Block {
+        files: Vec<Part<'a>>,
+        headerless: bool,
+    },
 }
 
 impl CatFileWriter {
-    pub fn new(input: String, output: &str) -> Self {
+    /// Without a profile, it's guessed from metadata.json.
+    pub fn new(input: String, profile: Option<Profile>) -> std::io::Result<Self> {
         let root = if input.ends_with("/") {
             input.clone()
         } else {
@@ -515,351 +1144,402 @@ impl CatFileWriter {
             format!("{}/metadata.json", input)
         };
 
-        let strbuf = std::fs::read_to_string(input).unwrap();
-        let entry: ArchiveEntry = serde_json::from_str(&strbuf).unwrap();
+        let strbuf = std::fs::read_to_string(&input).map_err(|e| std::io::Error::new(e.kind(), format!("{input}: {e}")))?;
+        let entry: ArchiveEntry = serde_json::from_str(&strbuf)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{input}: {e}")))?;
 
-        Self {
+        let profile = match profile {
+            Some(profile) => profile,
+            None => Profile::from_metadata(&entry),
+        };
+
+        Ok(Self {
             input: entry,
-            output: File::create(&output).unwrap(),
             root,
-        }
+            profile,
+        })
     }
 
-    pub fn pack(&mut self) -> std::io::Result<()> {
-        let entry = self.input.clone();
-        match &entry {
-            ArchiveEntry::Container(container) => self.pack_container(container),
-            _ => panic!("Unsupported entry: {:?}", entry),
+    /// Plans the whole archive, then writes it without seeking, so `output` can be a pipe.
+    pub fn pack<W: Write>(&self, output: &mut W) -> std::io::Result<()> {
+        let mut layout = Layout::default();
+        match &self.input {
+            ArchiveEntry::Container(container) => {
+                self.plan_container(&mut layout, container)?;
+            },
+            ArchiveEntry::Textures(_) | ArchiveEntry::TextureSet(_) | ArchiveEntry::TextureBlock(_) => {
+                let set = self.input.texture_set().unwrap();
+                let block = Part::Block {
+                    files: self.encode_textures(&set)?,
+                    headerless: set.headerless,
+                };
+                // older metadata.json don't have the byte order of the block
+                let endian = match &self.input {
+                    ArchiveEntry::TextureBlock(block) => block.endian,
+                    _ => self.profile.endian,
+                };
+                self.plan_part(&mut layout, endian, block)?;
+            },
+            _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Unsupported entry: {:?}", self.input))),
         }
-    }
 
-    fn pack_container(&mut self, container: &Container) -> std::io::Result<()> {
-        let start_of_container = self.get_offset();
-        self.write_header(container.version, container.format, container.size, container.alignment, container.children.len())?;
+        layout.write(output)?;
+        output.flush()
+    }
 
-        let start_of_children_offsets = start_of_container + container.size as u64 + 20;
-        let start_of_children_sizes = start_of_children_offsets + container.children.len() as u64 * 4;
+    /// Returns the size of the container, including the padding of its last child.
+    fn plan_container(&self, layout: &mut Layout, container: &Container) -> std::io::Result<u64> {
+        let mut parts = vec![];
 
         match container.format {
-            0 => {
-                for (id, child) in container.children.iter().enumerate() {
-                    let child_start = self.get_offset();
-                    let relative_child_start = (child_start - start_of_container) as u32 - container.size;
-                    self.write_at(start_of_children_offsets + id as u64 * 4, relative_child_start);
-                    match child {
-                        ArchiveEntry::Container(container) => self.pack_container(container)?,
-                        _ => panic!("Unsupported child: {:?}", child),
-                    }
-
-                    self.align(container.alignment);
-
-                    let child_size = self.get_offset() - child_start;
-                    self.write_at(start_of_children_sizes + id as u64 * 4, child_size as u32);
-                }
-            },
             1 | 3 | 4 => {
-                self.pack_format_1(start_of_container, &container)?;
+                if container.children.len() != 1 {
+                    return Err(unsupported(container, "one list of files"));
+                }
+                let Some(files) = container.children[0].named_files() else {
+                    return Err(unsupported(container, "one list of files"));
+                };
+
+                parts.push(Part::Bytes(self.names(&files)?));
+                for file in &files.files {
+                    let path = self.input_path(&file.filename)?;
+                    parts.push(match file.messages {
+                        Some(format) => Part::Bytes(MessageTable::from_csv(&std::fs::read(path)?, format)?.write(container.endian)?),
+                        None => Part::File(path),
+                    });
+                }
             },
             2 => {
-                let mut children_data = vec![];
-
-                for child in container.children.iter() {
-                    let start_of_child = self.get_offset();
-                    let relative_start_of_child = start_of_child - start_of_container - container.alignment as u64;
-
-                    self.pack_format_2(child)?;
-
-                    let child_size = self.get_offset() - start_of_child;
-
-                    children_data.push(ChildData {
-                        offset: relative_start_of_child,
-                        size: child_size,
-                    });
+                for child in &container.children {
+                    let Some(set) = child.texture_set() else {
+                        return Err(unsupported(container, "textures"));
+                    };
 
-                    self.align(container.alignment);
+                    parts.push(Part::SubContainer(self.sub_container(set.container, container), self.texture_set_parts(&set)?));
                 }
-
-                self.update_children_offsets_and_sizes(start_of_children_offsets, children_data)?;
+            },
+            5 => {
+                for child in &container.children {
+                    let part = match child {
+                        ArchiveEntry::File(file) | ArchiveEntry::Blob(Blob { filename: file, .. }) => Part::File(self.input_path(file)?),
+                        ArchiveEntry::Navmesh(file) => {
+                            let obj = std::fs::read_to_string(self.input_path(file)?)?;
+                            let mut data = vec![];
+                            Navmesh::from_obj(&obj)?.write(&mut data, container.endian)?;
+                            Part::Bytes(data)
+                        },
+                        ArchiveEntry::Script(file) => {
+                            let text = std::fs::read_to_string(self.input_path(file)?)?;
+                            Part::Bytes(script::write(&script::from_text(&text)?, container.endian))
+                        },
+                        _ => return Err(unsupported(container, "files, navmeshes and scripts")),
+                    };
+                    parts.push(part);
+                }
+            },
+            6 => {
+                let set = match container.children.as_slice() {
+                    [child] => child.texture_set(),
+                    _ => None,
+                };
+                let Some(set) = set else {
+                    return Err(unsupported(container, "one list of textures"));
+                };
+
+                parts = self.texture_set_parts(&set)?;
             },
             8 => {
-                let child_start = self.get_offset();
-                let relative_child_start = (child_start - start_of_container) as u32 - container.size;
-                self.write_at(start_of_children_offsets, relative_child_start);
-
-                self.pack_format_8(&container)?;
+                for child in &container.children {
+                    let Some(files) = child.named_files() else {
+                        return Err(unsupported(container, "models"));
+                    };
 
-                let child_size = self.get_offset() - child_start;
-                self.write_at(start_of_children_sizes, child_size as u32);
+                    parts.push(Part::SubContainer(self.sub_container(files.container, container), self.model_parts(&files)?));
+                }
+            },
+            _ => {
+                for child in &container.children {
+                    let part = match child {
+                        ArchiveEntry::Container(child) => Part::Container(child),
+                        ArchiveEntry::File(file) | ArchiveEntry::Blob(Blob { filename: file, .. }) => Part::File(self.input_path(file)?),
+                        ArchiveEntry::Messages(messages) => {
+                            let csv = std::fs::read(self.input_path(&messages.filename)?)?;
+                            Part::Bytes(MessageTable::from_csv(&csv, messages.format)?.write(container.endian)?)
+                        },
+                        _ => return Err(unsupported(container, "containers and files")),
+                    };
+                    parts.push(part);
+                }
             },
-            _ => panic!("Unhandled format: {}", container.format),
         };
 
-        let end_of_container = self.get_offset();
-        let container_size = (end_of_container - start_of_container) as u32 - container.size;
-        self.write_at(start_of_container + 16, container_size);
-
-        Ok(())
+        self.plan_children(layout, container.endian, container.version, container.format, container.size, container.alignment, parts)
     }
 
-    fn pack_format_1(&mut self, start_of_container: u64, container: &Container) -> std::io::Result<()> {
-        assert_eq!(container.children.len(), 1);
-        self.write_at(start_of_container + 0x18, 2);
-        self.write_at(start_of_container + 0x104, 2);
+    /// Plans the header then the children, each one padded to `alignment`.
+    #[allow(clippy::too_many_arguments)]
+    fn plan_children(&self, layout: &mut Layout, endian: Endian, version: u32, format: u32, size: u32, alignment: u32, parts: Vec<Part>) -> std::io::Result<u64> {
+        let start_of_container = layout.position();
+        let header_size = if size == 0 { 256 } else { size };
+        let count = parts.len();
 
-        let start_of_children_offsets = start_of_container + container.size as u64 + 20;
+        let header = layout.reserve(header_size as usize + 20 + count * 8);
+        layout.align(alignment);
 
-        let child = container.children.first().unwrap();
-        match child {
-            ArchiveEntry::Files(files) => {
-                let mut children_data = vec![];
+        let mut children_data = vec![];
+        for part in parts {
+            let start_of_child = layout.position();
+            let size_of_child = self.plan_part(layout, endian, part)?;
+            layout.align(alignment);
 
-                if container.format == 1 {
-                    let names = files.join(",\r\n");
-                    let names = format!("{names},\r\n");
-                    let relative_start_of_names = self.get_offset() - start_of_container - 256;
+            children_data.push(ChildData {
+                offset: start_of_child - start_of_container - header_size as u64,
+                size: size_of_child,
+            });
+        }
 
-                    write!(self.output, "{}", names)?;
-                    self.align(container.alignment);
+        let size_of_container = layout.position() - start_of_container;
+        let content_size = (size_of_container - header_size as u64) as u32;
+        layout.fill(header, container_header(endian, version, format, size, content_size, alignment, &children_data));
 
-                    children_data.push(ChildData {
-                        offset: relative_start_of_names,
-                        size: names.len() as u64,
-                    });
-                    self.align(container.alignment);
-                }
+        Ok(size_of_container)
+    }
 
+    /// Returns the size of the part, without padding.
+    fn plan_part(&self, layout: &mut Layout, endian: Endian, part: Part) -> std::io::Result<u64> {
+        match part {
+            Part::Bytes(bytes) => Ok(layout.push_bytes(bytes)),
+            Part::File(path) => layout.push_file(path),
+            Part::Container(container) => self.plan_container(layout, container),
+            Part::SubContainer(header, parts) => self.plan_children(layout, header.endian, header.version, 0, header.size, header.alignment, parts),
+            Part::Block { files, headerless: true } => {
+                let mut size_of_block = 0;
                 for file in files {
-                    let filename = format!("{}{}", self.root, file);
-                    let bin_file = std::fs::read(filename)?;
-                    let relative_start_of_file = self.get_offset() - start_of_container - 256;
-                    
-                    self.output.write(&bin_file)?;
-                    self.align(container.alignment);
-
-                    children_data.push(ChildData {
-                        offset: relative_start_of_file,
-                        size: bin_file.len() as u64,
-                    });
-                    self.align(container.alignment);
+                    size_of_block += self.plan_part(layout, endian, file)?;
                 }
-
-                self.update_children_offsets_and_sizes(start_of_children_offsets, children_data)?;
+                Ok(size_of_block)
             },
-            _ => panic!("Unsupported child: {:?}", child),
-        };
-
-        Ok(())
-    }
+            Part::Block { files, headerless: false } => {
+                let start_of_block = layout.position();
+                let count = files.len();
+                let header = layout.reserve(12 + count * 4);
 
-    fn pack_format_2(&mut self, child: &ArchiveEntry) -> std::io::Result<()> {
-        match child {
-            ArchiveEntry::Textures(textures) => {
-                let start_of_container = self.get_offset();
-                let start_of_children_offsets = start_of_container + 256 + 20;
-                let mut children_data = vec![];
-
-                self.write_header(1, 0, 256, 256, 2)?;
-
-                let names = textures.iter().map(|tex| tex.name.clone()).collect::<Vec<_>>().join(",\r\n");
-                let names = format!("{names},\r\n");
-                
-                let relative_start_of_names = self.get_offset() - start_of_container - 256;
-                children_data.push(ChildData {
-                    offset: relative_start_of_names,
-                    size: names.len() as u64,
-                });
-
-                write!(self.output, "{}", names)?;
-                self.align(256);
-
-                let start_of_image_block = self.get_offset();
-
-                let tex_count = textures.len() as u32;
-                self.output.write_u32::<LittleEndian>(12 + 4 * tex_count)?;
-                self.output.write_u32::<LittleEndian>(tex_count)?;
-                self.output.write_u32::<LittleEndian>(0x42424242)?;
-                for _ in 0..textures.len() {
-                    self.output.write_u32::<LittleEndian>(0x0)?;
+                let start_of_files = layout.position();
+                let mut offsets = vec![];
+                for file in files {
+                    offsets.push((layout.position() - start_of_files) as u32);
+                    self.plan_part(layout, endian, file)?;
                 }
 
-                let start_of_block_offsets = self.get_offset();
-                let mut children_offsets = vec![];
-
-                for texture in textures {
-                    children_offsets.push(self.get_offset() - start_of_block_offsets);
-
-                    let filename = format!("{}{}", self.root, texture.filename);
-                    let img = ImageReader::open(&filename).unwrap().decode().unwrap();
-                    let img = match img {
-                        DynamicImage::ImageRgba8(image) => image,
-                        DynamicImage::ImageRgb8(image) => {
-                            let rgba_image: RgbaImage = image.convert();
-                            rgba_image
-                        },
-                        _ => panic!("{} is not a RGB(A) image: {:?}", filename, img),
-                    };
-
-                    let dds = texture::dds_from_image(&img, &texture.format).unwrap();
-                    dds.write(&mut self.output).unwrap();
+                // the size of the block includes its header
+                let size_of_block = layout.position() - start_of_block;
+                let mut bytes = vec![];
+                for word in [12 + 4 * count as u32, count as u32, size_of_block as u32].into_iter().chain(offsets) {
+                    endian.write_u32(&mut bytes, word)?;
                 }
-                
-                let size_of_content_block = self.get_offset() - start_of_image_block;
-                self.write_at(start_of_image_block + 8, size_of_content_block as u32);
+                layout.fill(header, bytes);
 
-                for (id, offset) in children_offsets.iter().enumerate() {
-                    self.write_at(start_of_image_block + 12 + (id as u64 * 4), *offset as u32);
-                }
+                Ok(size_of_block)
+            },
+        }
+    }
 
-                self.align(256);
+    /// Header of a container in `parent`, the one of the profile if metadata.json is older than the headers of sub-containers.
+    fn sub_container(&self, header: Option<SubContainer>, parent: &Container) -> SubContainer {
+        header.unwrap_or(SubContainer {
+            endian: parent.endian,
+            version: 1,
+            size: 256,
+            alignment: self.profile.alignment,
+        })
+    }
 
-                let relative_start_of_image_block = start_of_image_block - start_of_container - 256;
-                children_data.push(ChildData {
-                    offset: relative_start_of_image_block,
-                    size: size_of_content_block,
-                });
+    /// Names then textures, in a container of format 2 or 6.
+    fn texture_set_parts(&self, set: &TextureSet) -> std::io::Result<Vec<Part<'static>>> {
+        let names = StringTable {
+            names: set.textures.iter().map(|tex| tex.name.clone()).collect(),
+            format: set.strings.clone(),
+        }.write()?;
+
+        Ok(vec![
+            Part::Bytes(names),
+            Part::Block {
+                files: self.encode_textures(set)?,
+                headerless: set.headerless,
+            },
+        ])
+    }
 
-                let container_size = (self.get_offset() - start_of_container) as u32 - 256;
-                self.write_at(start_of_container + 16, container_size);
+    /// Names then mtl.csv and the model, in a container of format 8.
+    fn model_parts(&self, files: &NamedFiles) -> std::io::Result<Vec<Part<'static>>> {
+        if files.files.len() != 2 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("A model has 2 files, not {}", files.files.len())));
+        }
 
-                self.update_children_offsets_and_sizes(start_of_children_offsets, children_data)?;
+        let mut parts = vec![];
+        for file in &files.files {
+            parts.push(Part::File(self.input_path(&file.filename)?));
+        }
+
+        Ok(vec![
+            Part::Bytes(self.names(files)?),
+            Part::Block {
+                files: parts,
+                headerless: files.headerless,
             },
-            _ => panic!("Unsupported child: {:?}", child),
-        };
+        ])
+    }
 
-        Ok(())
+    fn names(&self, files: &NamedFiles) -> std::io::Result<Vec<u8>> {
+        StringTable {
+            names: files.files.iter().map(|file| file.name.clone()).collect(),
+            format: files.strings.clone(),
+        }.write()
     }
 
-    fn pack_format_8(&mut self, container: &Container) -> std::io::Result<()> {
-        assert_eq!(container.children.len(), 1);
+    /// The textures of a set, in parallel.
+    fn encode_textures(&self, set: &TextureSet) -> std::io::Result<Vec<Part<'static>>> {
+        set.textures.par_iter()
+            .map(|texture| self.encode_texture(texture).map(Part::Bytes))
+            .collect()
+    }
 
-        let child = container.children.first().unwrap();
-        match child {
-            ArchiveEntry::Files(files) => {
-                assert_eq!(files.len(), 2);
+    /// DDS file of a texture, encoded from its PNG unless it was kept as it is.
+    fn encode_texture(&self, texture: &Texture) -> std::io::Result<Vec<u8>> {
+        let filename = self.input_path(&texture.filename)?;
+        if let TextureFormat::Raw(_) = texture.format {
+            return std::fs::read(filename);
+        } else if self.profile.textures != TextureCodec::Dds {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} can't be converted to {:?}", texture.filename, self.profile.textures)));
+        }
 
-                let start_of_container = self.get_offset();
-                let start_of_children_offsets = start_of_container + 256 + 20;
+        texture::dds_from_png(&filename, &texture.format)
+    }
 
-                self.write_header(1, 0, 256, 256, 2)?;
+    /// Path of a file listed in metadata.json, which must be inside the directory being packed.
+    fn input_path(&self, file: &str) -> std::io::Result<String> {
+        check_relative_path(file)?;
+        Ok(format!("{}{}", self.root, file))
+    }
+}
 
-                let mut children_data = vec![];
+/// Header of a container, from its start to the end of the sizes of its children.
+pub fn container_header(endian: Endian, version: u32, format: u32, size: u32, content_size: u32, alignment: u32, children: &[ChildData]) -> Vec<u8> {
+    let header_size = if size == 0 { 256 } else { size };
+    let count = children.len() as u32;
+    let mut header = vec![];
 
-                if container.format == 1 {
-                    let names = files.join(",\r\n");
-                    let names = format!("{names},\r\n");
-                    let relative_start_of_names = self.get_offset() - start_of_container - 256;
+    for word in [1, version, 0, size, content_size, format, count] {
+        endian.write_u32(&mut header, word).unwrap();
+    }
+    header.resize(header_size as usize, 0);
 
-                    write!(self.output, "{}", names)?;
-                    self.align(container.alignment);
+    // byte 0
+    for word in [0, count, format, alignment, 0] {
+        endian.write_u32(&mut header, word).unwrap();
+    }
+    for child in children {
+        endian.write_u32(&mut header, child.offset as u32).unwrap();
+    }
+    for child in children {
+        endian.write_u32(&mut header, child.size as u32).unwrap();
+    }
 
-                    children_data.push(ChildData {
-                        offset: relative_start_of_names,
-                        size: names.len() as u64,
-                    });
-                    self.align(container.alignment);
-                }
+    header
+}
 
-                for file in files {
-                    let filename = format!("{}{}", self.root, file);
-                    let bin_file = std::fs::read(filename)?;
-                    let relative_start_of_file = self.get_offset() - start_of_container - 256;
-                    
-                    self.output.write(&bin_file)?;
-                    self.align(container.alignment);
-
-                    children_data.push(ChildData {
-                        offset: relative_start_of_file,
-                        size: bin_file.len() as u64,
-                    });
-                    self.align(container.alignment);
-                }
+fn unsupported(container: &Container, expected: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("A container of format {} has {}, not {:?}", container.format, expected, container.children))
+}
 
-                let container_size = (self.get_offset() - start_of_container) as u32 - container.size;
-                self.write_at(start_of_container + 16, container_size);
+/// Converts the DDS textures to PNG, in parallel.
+fn decode_textures(images: Vec<(Dds, PathBuf)>) -> std::io::Result<()> {
+    images.into_par_iter().try_for_each(|(dds, path)| {
+        let image = image_from_dds(&dds, 0)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {e}", path.display())))?;
+        image.save(&path)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {e}", path.display())))
+    })
+}
 
-                self.update_children_offsets_and_sizes(start_of_children_offsets, children_data)?;
-            },
-            _ => panic!("Unsupported child: {:?}", child),
-        };
-        Ok(())
+fn check_format(header: &ContainerHeader, offset: u64, format: u32) -> std::io::Result<()> {
+    if header.format != format {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("The container at {:#X} is of format {}, expected {}", offset, header.format, format)));
     }
+    Ok(())
+}
 
-    fn write_header(&mut self, version: u32, format: u32, size: u32, alignment: u32, children_count: usize) -> std::io::Result<()> {
-        self.output.write_u32::<LittleEndian>(1)?;
-        self.output.write_u32::<LittleEndian>(version)?;
-        self.output.write_u32::<LittleEndian>(0)?;
-        self.output.write_u32::<LittleEndian>(size)?;
-        self.output.write_u32::<LittleEndian>(0)?;
-        self.output.write_u32::<LittleEndian>(format)?;
-        self.output.write_u32::<LittleEndian>(children_count as u32)?;
-        let size = if size == 0 { 256 } else { size };
-        for _ in 0..((size / 4) - 6) {
-            self.output.write_u32::<LittleEndian>(0)?;
-        }
-        self.output.write_u32::<LittleEndian>(children_count as u32)?;
-        self.output.write_u32::<LittleEndian>(format)?;
-        self.output.write_u32::<LittleEndian>(alignment)?;
-        self.output.write_u32::<LittleEndian>(0)?;
-        for _ in 0..children_count {
-            self.output.write_u32::<LittleEndian>(0)?;
-            self.output.write_u32::<LittleEndian>(0)?;
-        }
-        self.align(alignment);
-
-        Ok(())
+fn check_children(header: &ContainerHeader, offset: u64, count: usize) -> std::io::Result<()> {
+    if header.children.len() != count {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("The container at {:#X} has {} children, expected {}", offset, header.children.len(), count)));
     }
+    Ok(())
+}
 
-    fn get_offset(&mut self) -> u64 {
-        self.output.seek(SeekFrom::Current(0)).unwrap()
-    }
+fn read_dds(buffer: &[u8], offset: u64) -> std::io::Result<Dds> {
+    Dds::read(buffer).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid DDS at {offset:#X}: {e}")))
+}
 
-    fn write_at(&mut self, pos: u64, value: u32) {
-        let orig = self.get_offset();
-        self.goto(pos);
-        self.output.write_u32::<LittleEndian>(value).unwrap();
-        self.goto(orig);
-    }
+fn texture_format(dds: &Dds, offset: u64) -> std::io::Result<TextureFormat> {
+    dds.get_texture_format().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Unsupported DDS format at {offset:#X}: {:?} {:?}", dds.get_d3d_format(), dds.get_dxgi_format()))
+    })
+}
 
-    fn goto(&mut self, pos: u64) {
-        self.output.seek(SeekFrom::Start(pos)).unwrap();
+/// Node of a texture in the tree. Only DDS textures have a format, and the dimensions are unknown for the textures that aren't read.
+fn texture_node(file_type: FileType, dimensions: Option<(u32, u32)>, format: Option<TextureFormat>) -> NodeKind {
+    NodeKind::Texture {
+        file_type,
+        width: dimensions.map(|(width, _)| width),
+        height: dimensions.map(|(_, height)| height),
+        format,
     }
+}
 
-    fn update_children_offsets_and_sizes(&mut self, pos: u64, children: Vec<ChildData>) -> std::io::Result<()> {
-        let orig = self.get_offset();
-        self.goto(pos);
+/// Width and height from the IHDR chunk, which comes first.
+fn png_size(buffer: &[u8]) -> Option<(u32, u32)> {
+    let ihdr = buffer.get(16..24)?;
+    Some((BigEndian::read_u32(&ihdr[0..]), BigEndian::read_u32(&ihdr[4..])))
+}
 
-        for c in &children {
-            self.output.write_u32::<LittleEndian>(c.offset as u32).unwrap();
-        }
-        for c in &children {
-            self.output.write_u32::<LittleEndian>(c.size as u32).unwrap();
-        }
+/// Name of an extracted file, without the directories of its container.
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap()
+}
 
-        self.goto(orig);
-        Ok(())
+/// Replaces the characters that could escape the output directory or aren't allowed on Windows.
+fn sanitize_filename(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+        .collect();
+    let name = name.trim_end_matches(['.', ' ']);
+
+    if name.is_empty() {
+        "_".into()
+    } else {
+        name.into()
     }
+}
 
-    fn align(&mut self, alignment: u32) {
-        if alignment > 0 {
-            let alignment = alignment as u64;
-            let cur_pos = self.output.seek(SeekFrom::Current(0)).unwrap();
-            if (cur_pos % alignment) != 0 {
-                let cur_pos = (alignment - (cur_pos % alignment)) as i64;
-                for _ in 0..cur_pos {
-                    self.output.write_u8(0).unwrap();
-                }
-            }
-        } else {
-            println!("[DEBUG] Alignement is NULL");
-        }
+/// `name` with the number of a duplicate: before its extension if it's the extension of `filename` (`a (2).tmd`), at the end otherwise.
+fn numbered(name: &str, filename: &str, duplicate: usize) -> String {
+    let extension = |name: &str| match name.rfind('.') {
+        Some(pos) if pos > 0 => pos,
+        _ => name.len(),
+    };
+    let pos = extension(name);
+    if name[pos..] == filename[extension(filename)..] {
+        format!("{} ({duplicate}){}", &name[..pos], &name[pos..])
+    } else {
+        format!("{name} ({duplicate})")
     }
 }
 
-#[allow(unused)]
-pub const A001: u32 = 0x61303031u32;
-#[allow(unused)]
-pub const GNF : u32 = 0x474E4620u32;
-#[allow(unused)]
-pub const TMD0: u32 = 0x746D6430u32;
-#[allow(unused)]
-pub const TMO1: u32 = 0x746D6F31u32;
+fn check_relative_path(path: &str) -> std::io::Result<&Path> {
+    let relative = Path::new(path);
+    if relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        Ok(relative)
+    } else {
+        Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("'{path}' is outside of the archive's directory")))
+    }
+}
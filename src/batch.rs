@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use crate::endian::Endian;
+use crate::magic::FileType;
+
+/// Successes and failures of a command run on every .cat file of a directory, by format.
+#[derive(Default)]
+pub struct Batch {
+    formats: BTreeMap<String, Count>,
+    failures: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+struct Count {
+    succeeded: usize,
+    failed: usize,
+}
+
+impl Batch {
+    /// Runs `command` on the file at `path`, named `name` in the messages, and keeps going if it fails.
+    pub fn run(&mut self, path: &Path, name: &str, command: impl FnOnce() -> std::io::Result<()>) {
+        let count = self.formats.entry(format_of(path)).or_default();
+        match command() {
+            Ok(()) => count.succeeded += 1,
+            Err(e) => {
+                eprintln!("{name}: {e}");
+                count.failed += 1;
+                self.failures.push((name.to_string(), e.to_string()));
+            },
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let mut lines = vec![];
+        for (format, count) in &self.formats {
+            lines.push(match count.failed {
+                0 => format!("{format}: {} succeeded", count.succeeded),
+                failed => format!("{format}: {} succeeded, {failed} failed", count.succeeded),
+            });
+        }
+        if !self.failures.is_empty() {
+            lines.push("Failed:".into());
+            for (name, e) in &self.failures {
+                lines.push(format!("  {name}: {e}"));
+            }
+        }
+
+        let total: usize = self.formats.values().map(|count| count.succeeded + count.failed).sum();
+        lines.push(format!("{total} files, {} failed", self.failures.len()));
+        lines.join("\n")
+    }
+
+    pub fn result(&self) -> std::io::Result<()> {
+        let total: usize = self.formats.values().map(|count| count.succeeded + count.failed).sum();
+        match self.failures.len() {
+            0 => Ok(()),
+            failed => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{failed} of {total} files failed"))),
+        }
+    }
+}
+
+/// .cat files of `directory` and its subdirectories, sorted, relative to `directory`.
+pub fn cat_files(directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    add_cat_files(directory, Path::new(""), &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn add_cat_files(directory: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(directory.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            add_cat_files(directory, &path, files)?;
+        } else if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("cat")) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Format of the root container of the file, or its type if it isn't a container.
+fn format_of(path: &Path) -> String {
+    let read = || -> std::io::Result<String> {
+        let mut file = File::open(path)?;
+        let mut buffer = [0u8; 32];
+        let read = file.read(&mut buffer)?;
+
+        let Some(endian) = Endian::detect(&buffer) else {
+            return Ok(format!("{:?}", FileType::detect(&buffer[..read])));
+        };
+        // byte zero, after the header: 0, count, format
+        let size = match endian.u32_at(&buffer[12..]) {
+            0 => 256,
+            size => size as u64,
+        };
+        let mut words = [0u8; 12];
+        file.seek(SeekFrom::Start(size))?;
+        file.read_exact(&mut words)?;
+        Ok(format!("Format {}", endian.u32_at(&words[8..])))
+    };
+
+    read().unwrap_or_else(|_| "Unreadable".into())
+}
@@ -0,0 +1,149 @@
+use std::collections::{HashMap, HashSet};
+use image_dds::image::{self, RgbaImage};
+use image_dds::image_from_dds;
+use crate::Dds;
+use crate::archive::CatFileReader;
+use crate::tree::{human_size, Entry, Node, NodeKind};
+
+/// Differences between two archives, printed by `asuka diff`.
+pub struct Diff {
+    pub lines: Vec<String>,
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+}
+
+impl Diff {
+    /// Compares the entries of `new` to the entries of `old` with the same path.
+    pub fn of(old: &CatFileReader, new: &CatFileReader) -> std::io::Result<Self> {
+        let old_entries = old.tree().map(Node::entries).unwrap_or_default();
+        let new_entries = new.tree().map(Node::entries).unwrap_or_default();
+        let by_path: HashMap<&str, &Node> = new_entries.iter().map(|entry| (entry.path.as_str(), entry.node)).collect();
+
+        let mut diff = Self {
+            lines: vec![],
+            added: 0,
+            removed: 0,
+            changed: 0,
+            unchanged: 0,
+        };
+
+        for Entry { path, node, .. } in &old_entries {
+            match by_path.get(path.as_str()) {
+                None => {
+                    diff.lines.push(format!("- {path}"));
+                    diff.removed += 1;
+                },
+                Some(other) => match compare(old, node, new, other)? {
+                    Some(change) => {
+                        diff.lines.push(format!("~ {path}: {change}"));
+                        diff.changed += 1;
+                    },
+                    None => diff.unchanged += 1,
+                },
+            }
+        }
+
+        let old_paths: HashSet<&str> = old_entries.iter().map(|entry| entry.path.as_str()).collect();
+        for Entry { path, .. } in &new_entries {
+            if !old_paths.contains(path.as_str()) {
+                diff.lines.push(format!("+ {path}"));
+                diff.added += 1;
+            }
+        }
+
+        Ok(diff)
+    }
+
+    pub fn print(&self) {
+        for line in &self.lines {
+            println!("{line}");
+        }
+        println!("{} added, {} removed, {} changed, {} unchanged", self.added, self.removed, self.changed, self.unchanged);
+    }
+}
+
+/// What changed between two entries with the same path, `None` if nothing did.
+fn compare(old: &CatFileReader, old_node: &Node, new: &CatFileReader, new_node: &Node) -> std::io::Result<Option<String>> {
+    if let (NodeKind::Container { version, format, alignment, endian, .. }, NodeKind::Container { version: new_version, format: new_format, alignment: new_alignment, endian: new_endian, .. }) = (&old_node.kind, &new_node.kind) {
+        let mut changes = vec![];
+        if format != new_format {
+            changes.push(format!("format {format} → {new_format}"));
+        }
+        if version != new_version {
+            changes.push(format!("version {version} → {new_version}"));
+        }
+        if alignment != new_alignment {
+            changes.push(format!("alignment {alignment} → {new_alignment}"));
+        }
+        if endian != new_endian {
+            changes.push(format!("{endian:?} → {new_endian:?}"));
+        }
+        return Ok((!changes.is_empty()).then(|| changes.join(", ")));
+    }
+
+    let is_container = |node: &Node| matches!(node.kind, NodeKind::Container { .. });
+    if is_container(old_node) || is_container(new_node) {
+        let kind = |node: &Node| if is_container(node) { "container" } else { "file" };
+        return Ok(Some(format!("{} → {}", kind(old_node), kind(new_node))));
+    }
+
+    let old_bytes = old.bytes(old_node.offset, old_node.size)?;
+    let new_bytes = new.bytes(new_node.offset, new_node.size)?;
+    if old_bytes == new_bytes {
+        return Ok(None);
+    }
+
+    if let (Some(old_image), Some(new_image)) = (decode(old_bytes), decode(new_bytes)) {
+        return Ok(Some(pixel_diff(&old_image, &new_image)));
+    }
+
+    Ok(Some(byte_diff(old_bytes, new_bytes)))
+}
+
+/// Pixels of a DDS or PNG texture, `None` for the other files and the textures that can't be decoded.
+fn decode(bytes: &[u8]) -> Option<RgbaImage> {
+    match bytes.get(0..4)? {
+        b"DDS " => image_from_dds(&Dds::read(bytes).ok()?, 0).ok(),
+        b"\x89PNG" => Some(image::load_from_memory(bytes).ok()?.to_rgba8()),
+        _ => None,
+    }
+}
+
+fn pixel_diff(old: &RgbaImage, new: &RgbaImage) -> String {
+    if old.dimensions() != new.dimensions() {
+        return format!("{}x{} → {}x{}", old.width(), old.height(), new.width(), new.height());
+    }
+
+    let mut different = 0;
+    let mut max_difference = 0;
+    for (old_pixel, new_pixel) in old.pixels().zip(new.pixels()) {
+        let difference = old_pixel.0.iter().zip(new_pixel.0.iter()).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0);
+        if difference > 0 {
+            different += 1;
+            max_difference = max_difference.max(difference);
+        }
+    }
+
+    let total = old.width() as usize * old.height() as usize;
+    if different == 0 {
+        format!("same pixels, encoded differently, {}x{}", old.width(), old.height())
+    } else {
+        let percent = different as f64 * 100.0 / total as f64;
+        format!("{different} of {total} pixels differ ({percent:.1}%), by up to {max_difference}")
+    }
+}
+
+fn byte_diff(old: &[u8], new: &[u8]) -> String {
+    let common = old.len().min(new.len());
+    let different = old.iter().zip(new).filter(|(a, b)| a != b).count();
+    let first = old.iter().zip(new).position(|(a, b)| a != b).unwrap_or(common);
+
+    let size = if old.len() == new.len() {
+        human_size(old.len() as u64)
+    } else {
+        format!("{} → {}", human_size(old.len() as u64), human_size(new.len() as u64))
+    };
+    format!("{size}, {different} of {common} bytes differ, the first at {first:#X}")
+}
@@ -0,0 +1,518 @@
+use crate::archive::*;
+use crate::endian::Endian;
+use crate::game::*;
+use crate::layout::Layout;
+use crate::tree::*;
+use crate::magic::FileType;
+use crate::strings::StringTable;
+use crate::texture;
+use crate::TextureFormat;
+use crate::Navmesh;
+use crate::MessageTable;
+use crate::script;
+
+/// Edits an archive: the containers and blocks around the entry are rewritten with the new
+/// offsets and sizes, everything else is copied as it is.
+pub struct Edit<'a> {
+    reader: &'a CatFileReader,
+    /// Path of the archive, where the other entries are copied from.
+    input: String,
+    /// Indexes of the nodes from the root to the entry replaced, or to the container where an entry is added or removed.
+    indexes: Vec<usize>,
+    change: Change,
+    description: String,
+}
+
+enum Change {
+    /// New content of the entry.
+    Replace(Vec<u8>),
+    /// New entry at the end of the container.
+    Add {
+        name: String,
+        data: Vec<u8>,
+    },
+    /// Index of the entry in its container, not counting the string table.
+    Remove(usize),
+}
+
+/// Containers where entries can be added or removed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Entries {
+    /// Format 1, 3 and 4: names then files.
+    Files,
+    /// Format 6 and the sets of format 2: names then a block of textures.
+    Textures {
+        headerless: bool,
+    },
+}
+
+/// Child of an edited container or block.
+enum Source {
+    /// Copied from the archive, at an offset and of a size.
+    Range(u64, u64),
+    Bytes(Vec<u8>),
+}
+
+impl<'a> Edit<'a> {
+    /// `entry` is the path of the entry when extracting, without its extension: the indexes of its containers then its name (`2/0/tex_face`).
+    /// `file` is converted like when packing: PNG to DDS, OBJ to navmesh, text to script and CSV to messages.
+    pub fn replace(reader: &'a CatFileReader, input: &str, entry: &str, file: &str) -> std::io::Result<Self> {
+        let tree = tree(reader, input)?;
+        let Some(indexes) = find_entry(tree, entry) else {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No entry '{entry}' in '{input}'")));
+        };
+
+        let target = node_at(tree, &indexes);
+        let original = reader.bytes(target.offset, target.size)?;
+        let data = encode(target, original, file, container_endian(tree, &indexes))?;
+        // the files of a block without a header are found from the sizes in their headers
+        if let NodeKind::Block { headerless: true } = node_at(tree, &indexes[..indexes.len() - 1]).kind {
+            let codec = match target.kind {
+                NodeKind::Texture { file_type, .. } => TextureCodec::from_file_type(file_type),
+                _ => None,
+            };
+            check_texture(codec.unwrap_or(reader.profile().textures), true, file, &data)?;
+        }
+
+        Ok(Self {
+            reader,
+            input: input.to_string(),
+            indexes,
+            description: format!("{entry}: {} bytes replaced by {} bytes", target.size, data.len()),
+            change: Change::Replace(data),
+        })
+    }
+
+    /// Adds `file` named `name` at the end of the container at `container` (the indexes of the containers, like `1` or `2/0`, `/` for the root).
+    /// Containers of format 1, 3 and 4 take any file, texture sets take textures of the profile: PNG files are encoded to `format`.
+    /// Without a name, files are named after `file` and textures after its stem.
+    pub fn add(reader: &'a CatFileReader, input: &str, container: &str, name: Option<&str>, file: &str, format: &TextureFormat) -> std::io::Result<Self> {
+        let tree = tree(reader, input)?;
+        let Some(indexes) = find_container(tree, container) else {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No container '{container}' in '{input}'")));
+        };
+        let Some(entries) = entries(tree, &indexes) else {
+            return Err(not_editable(node_at(tree, &indexes)));
+        };
+
+        let path = std::path::Path::new(file);
+        let name = match (name, entries) {
+            (Some(name), _) => name.to_string(),
+            (None, Entries::Files) => path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            (None, Entries::Textures { .. }) => path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+        };
+
+        let mut table = string_table(reader, node_at(tree, &indexes))?;
+        if name.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "The name of an entry can't be empty"));
+        } else if table.names.contains(&name) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("The container '{container}' already has an entry named '{name}'")));
+        }
+        table.names.push(name.clone());
+        check_names(&table)?;
+
+        let data = match entries {
+            Entries::Files => std::fs::read(file)?,
+            Entries::Textures { headerless } => {
+                // the textures already in the set, otherwise the profile
+                let block = &node_at(tree, &indexes).children[1];
+                let codec = match block.children.first().map(|texture| &texture.kind) {
+                    Some(NodeKind::Texture { file_type, .. }) => TextureCodec::from_file_type(*file_type),
+                    _ => None,
+                };
+                texture_data(codec.unwrap_or(reader.profile().textures), headerless, file, format)?
+            },
+        };
+
+        Ok(Self {
+            reader,
+            input: input.to_string(),
+            indexes,
+            description: format!("{name}: {} bytes added to '{container}'", data.len()),
+            change: Change::Add {
+                name,
+                data,
+            },
+        })
+    }
+
+    /// Removes the entry at `entry` (like with `replace`), which must be a file of a container of format 1, 3 or 4 or a texture of a set.
+    pub fn remove(reader: &'a CatFileReader, input: &str, entry: &str) -> std::io::Result<Self> {
+        let tree = tree(reader, input)?;
+        let Some(mut indexes) = find_entry(tree, entry) else {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No entry '{entry}' in '{input}'")));
+        };
+
+        // the index in the block of a texture set, or after the names in a container of files
+        let index = indexes.pop().unwrap();
+        let parent = node_at(tree, &indexes);
+        let index = match (&parent.kind, index) {
+            (NodeKind::Block { .. }, _) => {
+                indexes.pop();
+                match entries(tree, &indexes) {
+                    Some(Entries::Textures { .. }) => index,
+                    _ => return Err(not_editable(node_at(tree, &indexes))),
+                }
+            },
+            (_, 1..) if entries(tree, &indexes) == Some(Entries::Files) => index - 1,
+            _ => return Err(not_editable(parent)),
+        };
+
+        let mut table = string_table(reader, node_at(tree, &indexes))?;
+        if table.names.len() == 1 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("'{entry}' is the only entry of its container")));
+        }
+        table.remove(index);
+        check_names(&table)?;
+
+        Ok(Self {
+            reader,
+            input: input.to_string(),
+            indexes,
+            description: format!("{entry}: removed"),
+            change: Change::Remove(index),
+        })
+    }
+
+    /// What is changed, to print.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn plan(&self, layout: &mut Layout) -> std::io::Result<()> {
+        self.plan_node(layout, self.reader.tree().unwrap(), &self.indexes, self.reader.profile().endian)?;
+        Ok(())
+    }
+
+    /// Returns the size of the node, including the padding of the last child of a container.
+    /// `endian` is the byte order of the container of the node.
+    fn plan_node(&self, layout: &mut Layout, node: &Node, indexes: &[usize], endian: Endian) -> std::io::Result<u64> {
+        let Some((&index, indexes)) = indexes.split_first() else {
+            return match &self.change {
+                Change::Replace(data) => Ok(layout.push_bytes(data.clone())),
+                _ => self.plan_edited(layout, node),
+            };
+        };
+
+        match &node.kind {
+            NodeKind::Container { endian, alignment, count, .. } => {
+                let endian = *endian;
+                if node.children.len() != *count {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("The children of the container at {:#X} aren't all known", node.offset)));
+                }
+
+                let header_size = self.header_size(node, endian)?;
+                let byte_zero = node.offset + header_size;
+
+                // the header is kept, only the content size and the children are updated
+                let table = byte_zero + 20;
+                let mut header = self.reader.bytes(node.offset, header_size + 20 + *count as u64 * 8)?.to_vec();
+                let start_of_container = layout.position();
+                let reserved = layout.reserve(header.len());
+                layout.align(*alignment);
+
+                for (id, child) in node.children.iter().enumerate() {
+                    let offset_at = table + id as u64 * 4;
+                    let size_at = table + (*count + id) as u64 * 4;
+
+                    let start_of_child = layout.position();
+                    let size_of_child = if id == index {
+                        self.plan_node(layout, child, indexes, endian)?
+                    } else {
+                        let offset = endian.u32_at(self.reader.bytes(offset_at, 4)?) as u64;
+                        let size = endian.u32_at(self.reader.bytes(size_at, 4)?) as u64;
+                        layout.push_range(self.input.clone(), byte_zero + offset, size)
+                    };
+                    layout.align(*alignment);
+
+                    let offset = (start_of_child - start_of_container - header_size) as u32;
+                    patch(&mut header, offset_at - node.offset, endian.to_bytes(offset));
+                    patch(&mut header, size_at - node.offset, endian.to_bytes(size_of_child as u32));
+                }
+
+                let content_size = (layout.position() - start_of_container - header_size) as u32;
+                patch(&mut header, 16, endian.to_bytes(content_size));
+                layout.fill(reserved, header);
+
+                Ok(layout.position() - start_of_container)
+            },
+            NodeKind::Block { headerless } => {
+                // blocks only have files, and entries are only added to or removed from containers
+                let Change::Replace(data) = &self.change else {
+                    return Err(not_editable(node));
+                };
+
+                let mut files: Vec<_> = node.children.iter().map(|child| Source::Range(child.offset, child.size)).collect();
+                files[index] = Source::Bytes(data.clone());
+                self.plan_block(layout, endian, *headerless, files)
+            },
+            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("The entry at {:#X} isn't a container", node.offset))),
+        }
+    }
+
+    /// Container where an entry is added or removed, its header and its string table are written again.
+    fn plan_edited(&self, layout: &mut Layout, node: &Node) -> std::io::Result<u64> {
+        let NodeKind::Container { endian, version, format, alignment, .. } = node.kind else {
+            return Err(not_editable(node));
+        };
+
+        let mut table = string_table(self.reader, node)?;
+        let headerless = match (format, node.children.get(1).map(|child| &child.kind)) {
+            (1 | 3 | 4, _) => None,
+            (_, Some(NodeKind::Block { headerless })) => Some(*headerless),
+            _ => return Err(not_editable(node)),
+        };
+        let mut files: Vec<_> = match headerless {
+            None => node.children[1..].iter().map(|child| Source::Range(child.offset, child.size)).collect(),
+            Some(_) => node.children[1].children.iter().map(|child| Source::Range(child.offset, child.size)).collect(),
+        };
+
+        match &self.change {
+            Change::Add { name, data } => {
+                table.names.push(name.clone());
+                files.push(Source::Bytes(data.clone()));
+            },
+            Change::Remove(index) => {
+                table.remove(*index);
+                files.remove(*index);
+            },
+            Change::Replace(_) => unreachable!(),
+        }
+
+        let header_size = self.header_size(node, endian)?;
+        let count = match headerless {
+            None => files.len() + 1,
+            Some(_) => 2,
+        };
+
+        let start_of_container = layout.position();
+        let reserved = layout.reserve(header_size as usize + 20 + count * 8);
+        layout.align(alignment);
+
+        let mut children = vec![];
+        let mut plan_child = |layout: &mut Layout, size_of_child: u64, start_of_child: u64| {
+            layout.align(alignment);
+            children.push(ChildData {
+                offset: start_of_child - start_of_container - header_size,
+                size: size_of_child,
+            });
+        };
+
+        let start_of_child = layout.position();
+        let size_of_names = layout.push_bytes(table.write()?);
+        plan_child(layout, size_of_names, start_of_child);
+        match headerless {
+            None => {
+                for file in files {
+                    let start_of_child = layout.position();
+                    let size_of_file = self.push_source(layout, file);
+                    plan_child(layout, size_of_file, start_of_child);
+                }
+            },
+            Some(headerless) => {
+                let start_of_child = layout.position();
+                let size_of_block = self.plan_block(layout, endian, headerless, files)?;
+                plan_child(layout, size_of_block, start_of_child);
+            },
+        }
+
+        let size_of_container = layout.position() - start_of_container;
+        let content_size = (size_of_container - header_size) as u32;
+        let size = endian.u32_at(self.reader.bytes(node.offset + 12, 4)?);
+        layout.fill(reserved, container_header(endian, version, format, size, content_size, alignment, &children));
+
+        Ok(size_of_container)
+    }
+
+    /// Returns the size of the block, including its header.
+    fn plan_block(&self, layout: &mut Layout, endian: Endian, headerless: bool, files: Vec<Source>) -> std::io::Result<u64> {
+        let start_of_block = layout.position();
+        let count = files.len();
+        let reserved = (!headerless).then(|| layout.reserve(12 + count * 4));
+
+        let start_of_files = layout.position();
+        let mut offsets = vec![];
+        for file in files {
+            offsets.push((layout.position() - start_of_files) as u32);
+            self.push_source(layout, file);
+        }
+
+        // the size of the block includes its header
+        let size_of_block = layout.position() - start_of_block;
+        if let Some(reserved) = reserved {
+            let mut bytes = vec![];
+            for word in [12 + 4 * count as u32, count as u32, size_of_block as u32].into_iter().chain(offsets) {
+                endian.write_u32(&mut bytes, word)?;
+            }
+            layout.fill(reserved, bytes);
+        }
+
+        Ok(size_of_block)
+    }
+
+    fn push_source(&self, layout: &mut Layout, source: Source) -> u64 {
+        match source {
+            Source::Range(offset, size) => layout.push_range(self.input.clone(), offset, size),
+            Source::Bytes(bytes) => layout.push_bytes(bytes),
+        }
+    }
+
+    fn header_size(&self, node: &Node, endian: Endian) -> std::io::Result<u64> {
+        Ok(match endian.u32_at(self.reader.bytes(node.offset + 12, 4)?) {
+            0 => 256,
+            size => size as u64,
+        })
+    }
+}
+
+fn tree<'a>(reader: &'a CatFileReader, input: &str) -> std::io::Result<&'a Node> {
+    reader.tree().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("'{input}' isn't unpacked")))
+}
+
+fn node_at<'a>(tree: &'a Node, indexes: &[usize]) -> &'a Node {
+    indexes.iter().fold(tree, |node, index| &node.children[*index])
+}
+
+/// Indexes of the nodes from `node` to the entry at `path` (a file, or a container like `2/0`), numbered like its
+/// extracted file when its name is used several times (`2/0/a (2).tmd`).
+fn find_entry(node: &Node, path: &str) -> Option<Vec<usize>> {
+    if let Some(entry) = node.entries().into_iter().find(|entry| entry.path == path && !entry.indexes.is_empty()) {
+        return Some(entry.indexes);
+    }
+
+    // string tables aren't entries, they are given by their index in their container
+    let (container, index) = path.rsplit_once('/').unwrap_or(("", path));
+    let mut indexes = find_container(node, container)?;
+    let index: usize = index.parse().ok()?;
+    if !matches!(node_at(node, &indexes).children.get(index)?.kind, NodeKind::Names { .. }) {
+        return None;
+    }
+    indexes.push(index);
+    Some(indexes)
+}
+
+/// Indexes of the nodes from `node` to the container at `path`, made of the indexes of the containers.
+fn find_container(node: &Node, path: &str) -> Option<Vec<usize>> {
+    let mut indexes = vec![];
+    let mut node = node;
+    for component in path.split('/').filter(|component| !component.is_empty()) {
+        node = node.children.iter().find(|child| {
+            matches!(child.kind, NodeKind::Container { .. }) && child.id.to_string() == component
+        })?;
+        indexes.push(node.id);
+    }
+
+    matches!(node.kind, NodeKind::Container { .. }).then_some(indexes)
+}
+
+/// What can be added to or removed from the container at `indexes`, `None` if nothing can.
+fn entries(tree: &Node, indexes: &[usize]) -> Option<Entries> {
+    let node = node_at(tree, indexes);
+    let NodeKind::Container { format, .. } = node.kind else {
+        return None;
+    };
+    let parent_format = match indexes.split_last() {
+        Some((_, parent)) => match node_at(tree, parent).kind {
+            NodeKind::Container { format, .. } => Some(format),
+            _ => None,
+        },
+        None => None,
+    };
+
+    match (format, parent_format, node.children.as_slice()) {
+        (1 | 3 | 4, _, [names, ..]) if matches!(names.kind, NodeKind::Names { .. }) => Some(Entries::Files),
+        (6, _, [names, block]) | (0, Some(2), [names, block]) if matches!(names.kind, NodeKind::Names { .. }) => match block.kind {
+            NodeKind::Block { headerless } => Some(Entries::Textures { headerless }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Byte order of the container of the node at `indexes`.
+fn container_endian(tree: &Node, indexes: &[usize]) -> Endian {
+    let mut endian = Endian::Little;
+    let mut node = tree;
+    for index in indexes {
+        if let NodeKind::Container { endian: container_endian, .. } = node.kind {
+            endian = container_endian;
+        }
+        node = &node.children[*index];
+    }
+    endian
+}
+
+fn not_editable(node: &Node) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Entries can only be added to or removed from containers of format 1, 3 and 4 and texture sets, not the entry at {:#X}", node.offset))
+}
+
+/// String table of a container, its first child.
+fn string_table(reader: &CatFileReader, container: &Node) -> std::io::Result<StringTable> {
+    let names = &container.children[0];
+    StringTable::read(reader.bytes(names.offset, names.size)?)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Not a string table at {:#X}", names.offset)))
+}
+
+/// The names must be read back the same, so they can't contain the separator.
+fn check_names(table: &StringTable) -> std::io::Result<()> {
+    match StringTable::read(&table.write()?) {
+        Some(read) if read.names == table.names => Ok(()),
+        _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("The names {:?} can't be written in the string table", table.names))),
+    }
+}
+
+/// Texture in the format of `codec`, PNG files are encoded to DDS.
+fn texture_data(codec: TextureCodec, headerless: bool, file: &str, format: &TextureFormat) -> std::io::Result<Vec<u8>> {
+    let data = if extension(file) == "png" && codec == TextureCodec::Dds {
+        texture::dds_from_png(file, format)?
+    } else {
+        std::fs::read(file)?
+    };
+
+    check_texture(codec, headerless, file, &data)?;
+    Ok(data)
+}
+
+/// Checks that `data`, read from `file`, can be a texture of the set.
+fn check_texture(codec: TextureCodec, headerless: bool, file: &str, data: &[u8]) -> std::io::Result<()> {
+    let file_type = FileType::detect(data);
+    if TextureCodec::from_file_type(file_type) != Some(codec) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("'{file}' is a {:?} file, the textures are {:?}", file_type, codec)));
+    }
+    // their sizes come from their headers
+    if headerless && texture::texture_size(data) != Some(data.len()) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("'{file}' can't be in a block without a header, only DDS and PNG files can")));
+    }
+
+    Ok(())
+}
+
+/// Content of `file` as it's stored in the archive, converted like when packing if the entry was converted when extracting.
+/// `endian` is the byte order of the container of the entry.
+fn encode(target: &Node, original: &[u8], file: &str, endian: Endian) -> std::io::Result<Vec<u8>> {
+    match (&target.kind, extension(file).as_str()) {
+        (NodeKind::Texture { file_type: FileType::Dds, format: Some(format), .. }, "png") => texture::dds_from_png(file, format),
+        (NodeKind::Navmesh { .. }, "obj") => {
+            let mut data = vec![];
+            Navmesh::from_obj(&std::fs::read_to_string(file)?)?.write(&mut data, endian)?;
+            Ok(data)
+        },
+        (NodeKind::Script { .. }, "txt") => Ok(script::write(&script::from_text(&std::fs::read_to_string(file)?)?, endian)),
+        (NodeKind::Messages { .. }, "csv") => {
+            let Some(table) = MessageTable::read(original, endian) else {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("The entry at {:#X} isn't a message table", target.offset)));
+            };
+            MessageTable::from_csv(&std::fs::read(file)?, table.format)?.write(endian)
+        },
+        _ => std::fs::read(file),
+    }
+}
+
+fn extension(file: &str) -> String {
+    std::path::Path::new(file).extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase()
+}
+
+fn patch(buffer: &mut [u8], position: u64, bytes: [u8; 4]) {
+    let position = position as usize;
+    buffer[position..position + 4].copy_from_slice(&bytes);
+}
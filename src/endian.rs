@@ -0,0 +1,50 @@
+use byteorder::*;
+use serde::*;
+use std::io::{Read, Write};
+
+/// Byte order of the containers: little-endian on PC, PS4 and Vita, big-endian on PS3 and Xbox 360.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endian {
+    /// Containers start with the word 1, which gives their byte order.
+    pub fn detect(buffer: &[u8]) -> Option<Self> {
+        match buffer.get(0..4)? {
+            [1, 0, 0, 0] => Some(Endian::Little),
+            [0, 0, 0, 1] => Some(Endian::Big),
+            _ => None,
+        }
+    }
+
+    pub fn u32_at(self, buffer: &[u8]) -> u32 {
+        match self {
+            Endian::Little => LittleEndian::read_u32(buffer),
+            Endian::Big => BigEndian::read_u32(buffer),
+        }
+    }
+
+    pub fn to_bytes(self, value: u32) -> [u8; 4] {
+        match self {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        }
+    }
+
+    pub fn read_u32<R: Read>(self, reader: &mut R) -> std::io::Result<u32> {
+        match self {
+            Endian::Little => reader.read_u32::<LittleEndian>(),
+            Endian::Big => reader.read_u32::<BigEndian>(),
+        }
+    }
+
+    pub fn write_u32<W: Write>(self, writer: &mut W, value: u32) -> std::io::Result<()> {
+        match self {
+            Endian::Little => writer.write_u32::<LittleEndian>(value),
+            Endian::Big => writer.write_u32::<BigEndian>(value),
+        }
+    }
+}
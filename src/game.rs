@@ -0,0 +1,181 @@
+use clap_derive::ValueEnum;
+use serde::*;
+use crate::endian::Endian;
+use crate::magic::FileType;
+use crate::archive::ArchiveEntry;
+use crate::TextureFormat;
+
+/// Games supported, each one with the platforms it was released on.
+#[derive(ValueEnum, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Game {
+    NeptuniaU,
+    Megatagmension,
+    NinjaWars,
+    /// Onechanbara Z: Kagura, PS3 and Xbox 360
+    OnechanbaraZ,
+    OnechanbaraZ2,
+    OneeChanbaraOrigin,
+    BurstRenewal,
+    EstivalVersus,
+    PeachBall,
+    PeachBeachSplash,
+    Reflexions,
+    ShinoviVersus,
+}
+
+/// Platform the files come from, the games released on the same platform share their formats.
+#[derive(ValueEnum, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    /// PC releases
+    Pc,
+    /// PS3 and Xbox 360 releases (big-endian)
+    Ps3,
+    /// PS4 releases (GNF textures)
+    Ps4,
+    /// PS Vita releases (GXT textures)
+    Vita,
+}
+
+/// Format of the textures, only DDS can be converted to PNG and back.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureCodec {
+    Dds,
+    Gnf,
+    Gxt,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    /// Given on the command line, it's never detected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game: Option<Game>,
+    pub platform: Platform,
+    pub endian: Endian,
+    pub textures: TextureCodec,
+    /// Alignment of the containers written from a texture set or a model, when metadata.json doesn't have their header.
+    pub alignment: u32,
+}
+
+impl Game {
+    /// The releases the files were seen in come first: the PC one, except for the PS3 games.
+    pub fn platforms(self) -> &'static [Platform] {
+        match self {
+            Game::NeptuniaU => &[Platform::Pc, Platform::Vita],
+            Game::Megatagmension => &[Platform::Pc, Platform::Vita, Platform::Ps4],
+            Game::NinjaWars => &[Platform::Pc, Platform::Ps4],
+            Game::OnechanbaraZ => &[Platform::Ps3],
+            Game::OnechanbaraZ2 => &[Platform::Pc, Platform::Ps4],
+            Game::OneeChanbaraOrigin => &[Platform::Pc, Platform::Ps4],
+            Game::BurstRenewal => &[Platform::Pc, Platform::Ps4],
+            Game::EstivalVersus => &[Platform::Pc, Platform::Ps4, Platform::Vita],
+            Game::PeachBall => &[Platform::Pc],
+            Game::PeachBeachSplash => &[Platform::Pc, Platform::Ps4],
+            Game::Reflexions => &[Platform::Pc],
+            Game::ShinoviVersus => &[Platform::Pc, Platform::Vita],
+        }
+    }
+
+    /// Profile of the release of the game on `platform`, its first release if not set.
+    pub fn profile(self, platform: Option<Platform>) -> std::io::Result<Profile> {
+        let platform = platform.unwrap_or(self.platforms()[0]);
+        if !self.platforms().contains(&platform) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{self:?} wasn't released on {platform:?}, only on {:?}", self.platforms())));
+        }
+
+        Ok(Profile {
+            game: Some(self),
+            ..platform.profile()
+        })
+    }
+}
+
+impl Platform {
+    pub fn profile(self) -> Profile {
+        let (endian, textures) = match self {
+            Platform::Pc => (Endian::Little, TextureCodec::Dds),
+            // the textures haven't been seen yet
+            Platform::Ps3 => (Endian::Big, TextureCodec::Dds),
+            Platform::Ps4 => (Endian::Little, TextureCodec::Gnf),
+            Platform::Vita => (Endian::Little, TextureCodec::Gxt),
+        };
+
+        Profile {
+            game: None,
+            platform: self,
+            endian,
+            textures,
+            // the same in every release seen so far
+            alignment: 256,
+        }
+    }
+}
+
+/// Profile given on the command line: the one of the release of `game` on `platform`, or the one of `platform`.
+/// `None` if neither is given, it's detected then.
+pub fn profile(game: Option<Game>, platform: Option<Platform>) -> std::io::Result<Option<Profile>> {
+    match (game, platform) {
+        (Some(game), platform) => game.profile(platform).map(Some),
+        (None, Some(platform)) => Ok(Some(platform.profile())),
+        (None, None) => Ok(None),
+    }
+}
+
+impl TextureCodec {
+    pub fn from_file_type(file_type: FileType) -> Option<Self> {
+        match file_type {
+            FileType::Dds | FileType::Png => Some(TextureCodec::Dds),
+            FileType::Gnf => Some(TextureCodec::Gnf),
+            FileType::Gxt => Some(TextureCodec::Gxt),
+            _ => None,
+        }
+    }
+}
+
+impl Profile {
+    /// Guessed from the start of an archive: big-endian containers come from the PS3, lone textures give their platform.
+    pub fn detect(buffer: &[u8]) -> Self {
+        let platform = match (Endian::detect(buffer), FileType::detect(buffer)) {
+            (Some(Endian::Big), _) => Platform::Ps3,
+            (None, FileType::Gnf) => Platform::Ps4,
+            (None, FileType::Gxt) => Platform::Vita,
+            _ => Platform::Pc,
+        };
+
+        platform.profile()
+    }
+
+    /// Guessed from metadata.json: the byte order of the root container or block, then the textures that were kept as they are.
+    pub fn from_metadata(entry: &ArchiveEntry) -> Self {
+        let endian = match entry {
+            ArchiveEntry::Container(container) => container.endian,
+            ArchiveEntry::TextureBlock(block) => block.endian,
+            _ => Endian::Little,
+        };
+        if endian == Endian::Big {
+            return Platform::Ps3.profile();
+        }
+
+        match raw_textures(entry).first() {
+            Some(TextureCodec::Gnf) => Platform::Ps4.profile(),
+            Some(TextureCodec::Gxt) => Platform::Vita.profile(),
+            _ => Platform::Pc.profile(),
+        }
+    }
+}
+
+fn raw_textures(entry: &ArchiveEntry) -> Vec<TextureCodec> {
+    match entry {
+        ArchiveEntry::Container(container) => container.children.iter().flat_map(raw_textures).collect(),
+        ArchiveEntry::Textures(textures) => textures.iter().filter_map(|texture| raw_codec(&texture.format)).collect(),
+        ArchiveEntry::TextureSet(set) => set.textures.iter().filter_map(|texture| raw_codec(&texture.format)).collect(),
+        ArchiveEntry::TextureBlock(block) => block.textures.iter().filter_map(|texture| raw_codec(&texture.format)).collect(),
+        _ => vec![],
+    }
+}
+
+fn raw_codec(format: &TextureFormat) -> Option<TextureCodec> {
+    match format {
+        TextureFormat::Raw(file_type) => TextureCodec::from_file_type(*file_type),
+        _ => None,
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+use serde::*;
+use crate::archive::*;
+use crate::game::Profile;
+use crate::tree::Node;
+
+/// Printed by `asuka info`.
+#[derive(Serialize, Debug)]
+pub struct Info {
+    pub file: String,
+    pub size: u64,
+    pub profile: Profile,
+    /// `None` if the file is a texture instead of a container.
+    pub root: Option<Root>,
+    #[serde(flatten)]
+    pub summary: Summary,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Root {
+    pub format: u32,
+    pub version: u32,
+    pub alignment: u32,
+    pub children: usize,
+}
+
+/// Printed by `asuka list --json`.
+#[derive(Serialize, Debug)]
+pub struct Listing<'a> {
+    /// Path of the file in the directory, when listing a directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub profile: Profile,
+    pub tree: Option<&'a Node>,
+}
+
+/// What an archive contains, counted from the entries of metadata.json.
+#[derive(Serialize, Debug, Default)]
+pub struct Summary {
+    /// Number of containers of each format.
+    pub containers: BTreeMap<u32, usize>,
+    pub textures: usize,
+    pub files: usize,
+}
+
+impl Info {
+    pub fn print(&self) {
+        println!("{}: {} bytes", self.file, self.size);
+        println!("{:?}", self.profile);
+        if let Some(root) = &self.root {
+            println!("Root: format {}, version {}, alignment {}, {} children", root.format, root.version, root.alignment, root.children);
+        }
+        for (format, count) in &self.summary.containers {
+            println!("Containers of format {format}: {count}");
+        }
+        println!("Textures: {}", self.summary.textures);
+        println!("Files: {}", self.summary.files);
+    }
+}
+
+impl Summary {
+    pub fn of(entry: &ArchiveEntry) -> Self {
+        let mut summary = Self::default();
+        summary.add(entry);
+        summary
+    }
+
+    fn add(&mut self, entry: &ArchiveEntry) {
+        match entry {
+            ArchiveEntry::Container(container) => {
+                *self.containers.entry(container.format).or_default() += 1;
+                for child in &container.children {
+                    self.add(child);
+                }
+            },
+            ArchiveEntry::Textures(textures) => self.textures += textures.len(),
+            ArchiveEntry::TextureSet(set) => self.textures += set.textures.len(),
+            ArchiveEntry::TextureBlock(block) => self.textures += block.textures.len(),
+            ArchiveEntry::Files(files) => self.files += files.len(),
+            ArchiveEntry::NamedFiles(files) => self.files += files.files.len(),
+            ArchiveEntry::File(_)
+            | ArchiveEntry::Navmesh(_)
+            | ArchiveEntry::Script(_)
+            | ArchiveEntry::Messages(_)
+            | ArchiveEntry::Blob(_) => self.files += 1,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Part of a packed archive, planned before anything is written.
+enum Piece {
+    Bytes(Vec<u8>),
+    /// Part of a file copied as it is, its size is read when planning.
+    File {
+        path: String,
+        offset: u64,
+        size: u64,
+    },
+    Zeros(u64),
+}
+
+/// Everything that will be written, in order, with the position of each piece known up front.
+///
+/// Headers are reserved first and filled once their children are planned, so the archive
+/// is written in one go, without seeking back.
+#[derive(Default)]
+pub struct Layout {
+    pieces: Vec<Piece>,
+    position: u64,
+}
+
+/// Bytes reserved in a `Layout`, to fill later.
+pub struct Reserved {
+    index: usize,
+    size: usize,
+}
+
+impl Layout {
+    /// Size of what's planned so far, which is where the next piece starts.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn push_bytes(&mut self, bytes: Vec<u8>) -> u64 {
+        let size = bytes.len() as u64;
+        self.position += size;
+        self.pieces.push(Piece::Bytes(bytes));
+        size
+    }
+
+    pub fn push_file(&mut self, path: String) -> std::io::Result<u64> {
+        let size = std::fs::metadata(&path)?.len();
+        Ok(self.push_range(path, 0, size))
+    }
+
+    /// `size` bytes of the file at `path`, starting at `offset`.
+    pub fn push_range(&mut self, path: String, offset: u64, size: u64) -> u64 {
+        self.position += size;
+        self.pieces.push(Piece::File { path, offset, size });
+        size
+    }
+
+    pub fn reserve(&mut self, size: usize) -> Reserved {
+        self.pieces.push(Piece::Bytes(vec![0; size]));
+        self.position += size as u64;
+        Reserved {
+            index: self.pieces.len() - 1,
+            size,
+        }
+    }
+
+    pub fn fill(&mut self, reserved: Reserved, bytes: Vec<u8>) {
+        assert_eq!(bytes.len(), reserved.size);
+        self.pieces[reserved.index] = Piece::Bytes(bytes);
+    }
+
+    pub fn align(&mut self, alignment: u32) {
+        if alignment > 0 {
+            let alignment = alignment as u64;
+            if !self.position.is_multiple_of(alignment) {
+                let padding = alignment - (self.position % alignment);
+                self.position += padding;
+                self.pieces.push(Piece::Zeros(padding));
+            }
+        } else {
+            eprintln!("[DEBUG] Alignement is NULL");
+        }
+    }
+
+    pub fn write<W: Write>(&self, output: &mut W) -> std::io::Result<()> {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Bytes(bytes) => output.write_all(bytes)?,
+                Piece::File { path, offset, size } => {
+                    let mut file = File::open(path)?;
+                    file.seek(SeekFrom::Start(*offset))?;
+                    let copied = std::io::copy(&mut file.take(*size), output)?;
+                    if copied != *size {
+                        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, format!("'{path}' changed while packing")));
+                    }
+                },
+                Piece::Zeros(size) => {
+                    std::io::copy(&mut std::io::repeat(0).take(*size), output)?;
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(layout: &Layout) -> Vec<u8> {
+        let mut output = vec![];
+        layout.write(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn header_is_filled_after_its_children() {
+        let mut layout = Layout::default();
+        let header = layout.reserve(4);
+        layout.push_bytes(vec![1, 2, 3]);
+        layout.align(4);
+        let child = layout.position();
+        layout.push_bytes(vec![4]);
+        assert_eq!(layout.position(), 9);
+
+        layout.fill(header, (child as u32).to_le_bytes().to_vec());
+        assert_eq!(written(&layout), [8, 0, 0, 0, 1, 2, 3, 0, 4]);
+    }
+
+    #[test]
+    fn range_of_a_file_is_copied() {
+        let path = std::env::temp_dir().join(format!("asuka-layout-{}", std::process::id()));
+        std::fs::write(&path, b"0123456789").unwrap();
+
+        let mut layout = Layout::default();
+        layout.push_bytes(b"ab".to_vec());
+        layout.push_range(path.to_string_lossy().into_owned(), 3, 4);
+        layout.push_file(path.to_string_lossy().into_owned()).unwrap();
+        let output = written(&layout);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(layout.position(), 16);
+        assert_eq!(output, b"ab34560123456789");
+    }
+
+    #[test]
+    #[should_panic]
+    fn reserved_size_must_be_filled() {
+        let mut layout = Layout::default();
+        let header = layout.reserve(4);
+        layout.fill(header, vec![0; 8]);
+    }
+}
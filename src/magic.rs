@@ -0,0 +1,75 @@
+use byteorder::*;
+use serde::*;
+use crate::endian::Endian;
+
+pub const A001: u32 = 0x61303031u32;
+pub const DDS : u32 = 0x44445320u32;
+pub const GNF : u32 = 0x474E4620u32;
+pub const GXT : u32 = 0x47585400u32;
+pub const PNG : u32 = 0x89504E47u32;
+pub const TMD0: u32 = 0x746D6430u32;
+pub const TMO1: u32 = 0x746D6F31u32;
+
+/// Type of a file, guessed from its first bytes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileType {
+    Dds,
+    Png,
+    Gnf,
+    Gxt,
+    Tmd0,
+    Tmo1,
+    A001,
+    Container,
+    Unknown,
+}
+
+impl FileType {
+    pub fn detect(buffer: &[u8]) -> Self {
+        if buffer.len() < 4 {
+            return FileType::Unknown;
+        }
+
+        match BigEndian::read_u32(buffer) {
+            DDS => FileType::Dds,
+            PNG => FileType::Png,
+            GNF => FileType::Gnf,
+            GXT => FileType::Gxt,
+            TMD0 => FileType::Tmd0,
+            TMO1 => FileType::Tmo1,
+            A001 => FileType::A001,
+            _ if is_container(buffer) => FileType::Container,
+            _ => FileType::Unknown,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FileType::Dds => "dds",
+            FileType::Png => "png",
+            FileType::Gnf => "gnf",
+            FileType::Gxt => "gxt",
+            FileType::Tmd0 => "tmd",
+            FileType::Tmo1 => "tmo",
+            FileType::A001 => "a001",
+            FileType::Container => "cat",
+            FileType::Unknown => "bin",
+        }
+    }
+}
+
+/// Same checks as `CatFileReader::read_header` and `CatFileReader::unpack_container`.
+fn is_container(buffer: &[u8]) -> bool {
+    if buffer.len() < 32 {
+        return false;
+    }
+
+    let Some(endian) = Endian::detect(buffer) else {
+        return false;
+    };
+
+    let size = endian.u32_at(&buffer[12..]);
+    endian.u32_at(&buffer[4..]) < 3
+        && endian.u32_at(&buffer[8..]) == 0
+        && (size == 0 || size >= 32)
+}
@@ -2,71 +2,680 @@ use crate::ddsfile::*;
 use image_dds::*;
 use std::path::Path;
 use std::fs::File;
+use std::io::{BufWriter, Write};
 use clap::Parser;
-use clap_derive::Parser;
+use clap_derive::{Parser, Subcommand};
 use image_dds::ddsfile::Dds;
 
 mod archive;
 use archive::*;
 
+mod endian;
+
+mod layout;
+use layout::Layout;
+
+mod magic;
+
+mod game;
+use game::{Game, Platform, Profile};
+
+mod strings;
+
 mod texture;
 use texture::*;
 
+mod navmesh;
+use navmesh::*;
+
+mod material;
+use material::*;
+
+mod script;
+use script::ScriptItem;
+
+mod messages;
+use messages::*;
+
+mod a001;
+use a001::*;
+
+mod info;
+use info::*;
+
+mod tree;
+
+mod edit;
+use edit::Edit;
+
+mod diff;
+use diff::Diff;
+
+mod patch;
+use patch::Patch;
+
+mod batch;
+use batch::*;
+
 #[derive(Parser, Debug)]
 #[command(author = None, version = None, about = None, long_about = None)]
 struct Args {
-    /// Path to a .cat file to analyse/extract or folder to pack into a .cat file (if --pack is set)
-    input: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the structure of a .cat file, or of every .cat file of a directory
+    List {
+        /// .cat file or directory
+        input: String,
+
+        /// Game the file comes from
+        #[arg(short, long)]
+        game: Option<Game>,
+
+        /// Platform of the release of the game (its first one if not set), or platform the file comes from without a game.
+        /// The profile is detected if neither is set
+        #[arg(short, long)]
+        platform: Option<Platform>,
+
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a summary of a .cat file
+    Info {
+        input: String,
+
+        /// Game the file comes from
+        #[arg(short, long)]
+        game: Option<Game>,
+
+        /// Platform of the release of the game (its first one if not set), or platform the file comes from without a game.
+        /// The profile is detected if neither is set
+        #[arg(short, long)]
+        platform: Option<Platform>,
+
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Extract a .cat file to a directory, with a metadata.json to pack it back
+    Extract {
+        /// .cat file, or directory to extract every .cat file of, each in the same subdirectory in the output
+        input: String,
+
+        /// Directory where to extract the .cat file
+        output: String,
+
+        /// Game the file comes from
+        #[arg(short, long)]
+        game: Option<Game>,
+
+        /// Platform of the release of the game (its first one if not set), or platform the file comes from without a game.
+        /// The profile is detected if neither is set
+        #[arg(short, long)]
+        platform: Option<Platform>,
+
+        /// Only extract the entries with this name or path, like `swim_blend00_EE`, `2/0/tex_face` or `2/*/tex_*` (can be repeated)
+        #[arg(long)]
+        only: Vec<String>,
+    },
+    /// Pack an extracted directory into a .cat file
+    Pack {
+        /// Directory containing metadata.json
+        input: String,
+
+        /// .cat file to create, `-` for stdout
+        output: String,
+
+        /// Game the file is for
+        #[arg(short, long)]
+        game: Option<Game>,
+
+        /// Platform of the release of the game (its first one if not set), or platform the file is for without a game.
+        /// The profile is detected from metadata.json if neither is set
+        #[arg(short, long)]
+        platform: Option<Platform>,
+    },
+    /// Replace an entry of a .cat file by another file, without extracting the other entries
+    Replace {
+        input: String,
+
+        /// Path of the entry: the indexes of its containers then its name, like `2/0/tex_face`
+        entry: String,
+
+        /// New content of the entry, PNG files are converted to the format of the DDS they replace
+        file: String,
+
+        /// Game the file comes from
+        #[arg(short, long)]
+        game: Option<Game>,
+
+        /// Platform of the release of the game (its first one if not set), or platform the file comes from without a game.
+        /// The profile is detected if neither is set
+        #[arg(short, long)]
+        platform: Option<Platform>,
+    },
+    /// Add a file to a container of format 1, 3 or 4, or a texture to a texture set
+    Add {
+        input: String,
+
+        /// Path of the container: the indexes of the containers, like `1` or `2/0`, `/` for the root
+        container: String,
+
+        /// File to add, PNG files are converted to DDS in texture sets
+        file: String,
+
+        /// Name in the string table, the name of the file if not set (without extension for textures)
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Compression of the DDS, when adding a PNG to a texture set
+        #[arg(short, long, default_value = "dxt5")]
+        format: Compression,
+
+        /// Game the file comes from
+        #[arg(short, long)]
+        game: Option<Game>,
+
+        /// Platform of the release of the game (its first one if not set), or platform the file comes from without a game.
+        /// The profile is detected if neither is set
+        #[arg(short, long)]
+        platform: Option<Platform>,
+    },
+    /// Remove a file from a container of format 1, 3 or 4, or a texture from a texture set
+    Remove {
+        input: String,
+
+        /// Path of the entry: the indexes of its containers then its name, like `2/0/tex_face`
+        entry: String,
+
+        /// Game the file comes from
+        #[arg(short, long)]
+        game: Option<Game>,
 
-    /// Path to a folder where to extract the .cat file
-    #[arg(short, long, conflicts_with="pack")]
-    extract: Option<String>,
+        /// Platform of the release of the game (its first one if not set), or platform the file comes from without a game.
+        /// The profile is detected if neither is set
+        #[arg(short, long)]
+        platform: Option<Platform>,
+    },
+    /// Compare two .cat files entry by entry, with the pixels of the textures that changed
+    Diff {
+        old: String,
 
-    /// Path to a directory that will be packed in a .cat file
-    #[arg(short, long, conflicts_with="extract")]
-    pack: Option<String>,
+        new: String,
+
+        /// Game the files come from
+        #[arg(short, long)]
+        game: Option<Game>,
+
+        /// Platform of the release of the game (its first one if not set), or platform the files come from without a game.
+        /// The profile is detected if neither is set
+        #[arg(short, long)]
+        platform: Option<Platform>,
+    },
+    /// Write a patch with the entries of a modded .cat file that aren't in the original
+    Mkpatch {
+        original: String,
+
+        modded: String,
+
+        /// .asp file to create
+        output: String,
+
+        /// Game the files come from
+        #[arg(short, long)]
+        game: Option<Game>,
+
+        /// Platform of the release of the game (its first one if not set), or platform the files come from without a game.
+        /// The profile is detected if neither is set
+        #[arg(short, long)]
+        platform: Option<Platform>,
+    },
+    /// Apply a patch made by `mkpatch` to the original .cat file
+    Apply {
+        input: String,
+
+        /// .asp file
+        patch: String,
+
+        /// Game the file comes from
+        #[arg(short, long)]
+        game: Option<Game>,
+
+        /// Platform of the release of the game (its first one if not set), or platform the file comes from without a game.
+        /// The profile is detected if neither is set
+        #[arg(short, long)]
+        platform: Option<Platform>,
+    },
+    /// Check that a .cat file can be read and that its containers are consistent
+    Verify {
+        /// .cat file or directory
+        input: String,
+
+        /// Game the file comes from
+        #[arg(short, long)]
+        game: Option<Game>,
+
+        /// Platform of the release of the game (its first one if not set), or platform the file comes from without a game.
+        /// The profile is detected if neither is set
+        #[arg(short, long)]
+        platform: Option<Platform>,
+    },
+    /// Convert a texture: DDS to PNG or PNG to DDS
+    Convert {
+        input: String,
+
+        output: String,
+
+        /// Compression of the DDS, when converting from PNG
+        #[arg(short, long, default_value = "dxt5")]
+        format: Compression,
+    },
 }
 
 fn main() {
     let args = Args::parse();
 
-    let path = Path::new(&args.input);
+    let result = match args.command {
+        Command::List { input, game, platform, json } => game::profile(game, platform).and_then(|profile| list(&input, profile, json)),
+        Command::Info { input, game, platform, json } => game::profile(game, platform).and_then(|profile| info(&input, profile, json)),
+        Command::Extract { input, output, game, platform, only } => game::profile(game, platform).and_then(|profile| extract(&input, &output, profile, &only)),
+        Command::Pack { input, output, game, platform } => game::profile(game, platform).and_then(|profile| pack(&input, &output, profile)),
+        Command::Replace { input, entry, file, game, platform } => game::profile(game, platform).and_then(|profile| replace(&input, &entry, &file, profile)),
+        Command::Add { input, container, file, name, format, game, platform } => game::profile(game, platform).and_then(|profile| add(&input, &container, &file, name, format, profile)),
+        Command::Remove { input, entry, game, platform } => game::profile(game, platform).and_then(|profile| remove(&input, &entry, profile)),
+        Command::Diff { old, new, game, platform } => game::profile(game, platform).and_then(|profile| diff(&old, &new, profile)),
+        Command::Mkpatch { original, modded, output, game, platform } => game::profile(game, platform).and_then(|profile| mkpatch(&original, &modded, &output, profile)),
+        Command::Apply { input, patch, game, platform } => game::profile(game, platform).and_then(|profile| apply(&input, &patch, profile)),
+        Command::Verify { input, game, platform } => game::profile(game, platform).and_then(|profile| verify(&input, profile)),
+        Command::Convert { input, output, format } => convert(&input, &output, format),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn list(input: &str, profile: Option<Profile>, json: bool) -> std::io::Result<()> {
+    if Path::new(input).is_dir() {
+        return list_directory(input, profile, json);
+    }
+    check_file(input)?;
+
+    let mut reader = CatFileReader::new(input, None, profile)?;
+    reader.unpack()?;
+
+    if json {
+        let listing = Listing {
+            file: None,
+            profile: reader.profile(),
+            tree: reader.tree(),
+        };
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &listing)?;
+        println!();
+        return Ok(());
+    }
+
+    println!("{:?}", reader.profile());
+    if let Some(tree) = reader.tree() {
+        tree.print();
+    }
+
+    Ok(())
+}
+
+/// Lists every .cat file of the directory, as one JSON array with `--json`.
+fn list_directory(input: &str, profile: Option<Profile>, json: bool) -> std::io::Result<()> {
+    let directory = Path::new(input);
+    let mut batch = Batch::default();
+    let mut listed = 0;
+
+    if json {
+        print!("[");
+    }
+    for file in cat_files(directory)? {
+        let path = directory.join(&file);
+        let name = file.display().to_string();
+        batch.run(&path, &name, || {
+            let mut reader = CatFileReader::new(&path.to_string_lossy(), None, profile)?;
+            reader.unpack()?;
+
+            if json {
+                let listing = Listing {
+                    file: Some(name.clone()),
+                    profile: reader.profile(),
+                    tree: reader.tree(),
+                };
+                println!("{}", if listed == 0 { "" } else { "," });
+                serde_json::to_writer_pretty(std::io::stdout().lock(), &listing)?;
+            } else {
+                println!("{name}: {:?}", reader.profile());
+                if let Some(tree) = reader.tree() {
+                    tree.print();
+                }
+                println!();
+            }
+            listed += 1;
+            Ok(())
+        });
+    }
+
+    // stdout only has the JSON
+    if json {
+        println!("\n]");
+        eprintln!("{}", batch.summary());
+    } else {
+        println!("{}", batch.summary());
+    }
+    batch.result()
+}
+
+fn info(input: &str, profile: Option<Profile>, json: bool) -> std::io::Result<()> {
+    check_file(input)?;
+
+    let mut reader = CatFileReader::new(input, None, profile)?;
+    let entry = reader.unpack()?;
+
+    let info = Info {
+        file: input.to_string(),
+        size: std::fs::metadata(input)?.len(),
+        profile: reader.profile(),
+        root: match &entry {
+            ArchiveEntry::Container(container) => Some(Root {
+                format: container.format,
+                version: container.version,
+                alignment: container.alignment,
+                children: container.children.len(),
+            }),
+            _ => None,
+        },
+        summary: Summary::of(&entry),
+    };
+
+    if json {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &info)?;
+        println!();
+    } else {
+        info.print();
+    }
+
+    Ok(())
+}
+
+fn extract(input: &str, output: &str, profile: Option<Profile>, only: &[String]) -> std::io::Result<()> {
+    if Path::new(input).is_dir() {
+        return extract_directory(input, output, profile, only);
+    }
+    check_file(input)?;
+
+    let selected = extract_archive(input, output, profile, only)?;
+    if only.is_empty() {
+        println!("OK");
+    } else if selected.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No entry matches {:?}", only)));
+    }
+    for path in selected {
+        println!("{path}");
+    }
+
+    Ok(())
+}
+
+/// Extracts every .cat file of the directory to the same path in `output`, without the extension.
+fn extract_directory(input: &str, output: &str, profile: Option<Profile>, only: &[String]) -> std::io::Result<()> {
+    let directory = Path::new(input);
+    let mut batch = Batch::default();
+    let mut selected = 0;
+
+    for file in cat_files(directory)? {
+        let path = directory.join(&file);
+        let name = file.display().to_string();
+        let relative_output = file.with_extension("").display().to_string();
+        batch.run(&path, &name, || {
+            let paths = extract_archive(&path.to_string_lossy(), &format!("{output}/{relative_output}"), profile, only)?;
+            if only.is_empty() {
+                println!("{name}: OK");
+            }
+            for path in &paths {
+                println!("{relative_output}/{path}");
+            }
+            selected += paths.len();
+            Ok(())
+        });
+    }
+
+    println!("{}", batch.summary());
+    batch.result()?;
+    if !only.is_empty() && selected == 0 {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No entry matches {:?}", only)));
+    }
+
+    Ok(())
+}
+
+/// Returns the sorted paths of the entries that were extracted, when only some are.
+fn extract_archive(input: &str, output: &str, profile: Option<Profile>, only: &[String]) -> std::io::Result<Vec<String>> {
+    let mut reader = CatFileReader::new(input, Some(output.to_string()), profile)?;
+    if !only.is_empty() {
+        reader.select(only)?;
+    }
+    let entry = reader.unpack()?;
+
+    // without every file, the directory can't be packed
+    if !only.is_empty() {
+        let mut paths: Vec<_> = reader.selected_paths().iter().cloned().collect();
+        paths.sort();
+        return Ok(paths);
+    }
+
+    std::fs::create_dir_all(output)?;
+    let writer = BufWriter::new(File::create(format!("{output}/metadata.json"))?);
+    serde_json::to_writer_pretty(writer, &entry)?;
+
+    Ok(vec![])
+}
+
+fn pack(input: &str, output: &str, profile: Option<Profile>) -> std::io::Result<()> {
+    if !Path::new(input).is_dir() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("'{input}' is not a directory.")));
+    }
+
+    let writer = CatFileWriter::new(input.to_string(), profile)?;
+    // "-" writes the archive to stdout
+    if output == "-" {
+        writer.pack(&mut std::io::stdout().lock())
+    } else {
+        writer.pack(&mut BufWriter::new(File::create(output)?))?;
+        println!("OK");
+        Ok(())
+    }
+}
+
+fn replace(input: &str, entry: &str, file: &str, profile: Option<Profile>) -> std::io::Result<()> {
+    check_file(file)?;
+
+    edit(input, profile, |reader, layout| plan(Edit::replace(reader, input, entry, file)?, layout))
+}
+
+fn add(input: &str, container: &str, file: &str, name: Option<String>, format: Compression, profile: Option<Profile>) -> std::io::Result<()> {
+    check_file(file)?;
+
+    let format = TextureFormat::D3DFormat(format, format.pixel_format());
+    edit(input, profile, |reader, layout| plan(Edit::add(reader, input, container, name.as_deref(), file, &format)?, layout))
+}
+
+fn remove(input: &str, entry: &str, profile: Option<Profile>) -> std::io::Result<()> {
+    edit(input, profile, |reader, layout| plan(Edit::remove(reader, input, entry)?, layout))
+}
+
+fn plan(edit: Edit, layout: &mut Layout) -> std::io::Result<String> {
+    edit.plan(layout)?;
+    Ok(edit.description().to_string())
+}
+
+/// Writes the edited archive next to `input`, which is read while writing, then replaces it.
+/// `change` plans the edited archive and returns what to print.
+fn edit(input: &str, profile: Option<Profile>, change: impl FnOnce(&CatFileReader, &mut Layout) -> std::io::Result<String>) -> std::io::Result<()> {
+    check_file(input)?;
+
+    let temporary = format!("{input}.tmp");
+    let result = (|| {
+        let mut reader = CatFileReader::new(input, None, profile)?;
+        reader.unpack()?;
+
+        let mut layout = Layout::default();
+        let description = change(&reader, &mut layout)?;
+
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        layout.write(&mut writer)?;
+        writer.flush()?;
+
+        Ok(description)
+    })();
+
+    // the original isn't mapped anymore
+    match result {
+        Ok(description) => {
+            std::fs::rename(&temporary, input)?;
+            println!("{description}");
+            Ok(())
+        },
+        Err(e) => {
+            let _ = std::fs::remove_file(&temporary);
+            Err(e)
+        },
+    }
+}
+
+fn diff(old: &str, new: &str, profile: Option<Profile>) -> std::io::Result<()> {
+    check_file(old)?;
+    check_file(new)?;
+
+    let mut old_reader = CatFileReader::new(old, None, profile)?;
+    old_reader.unpack()?;
+    let mut new_reader = CatFileReader::new(new, None, profile)?;
+    new_reader.unpack()?;
+
+    Diff::of(&old_reader, &new_reader)?.print();
+
+    Ok(())
+}
+
+fn mkpatch(original: &str, modded: &str, output: &str, profile: Option<Profile>) -> std::io::Result<()> {
+    check_file(original)?;
+    check_file(modded)?;
+
+    let mut original_reader = CatFileReader::new(original, None, profile)?;
+    original_reader.unpack()?;
+    let mut modded_reader = CatFileReader::new(modded, None, profile)?;
+    modded_reader.unpack()?;
+
+    let patch = Patch::make(&original_reader, &modded_reader, std::fs::metadata(modded)?.len())?;
+    let mut writer = BufWriter::new(File::create(output)?);
+    patch.write(&mut writer)?;
+    writer.flush()?;
+    println!("{output}: {}", patch.describe());
+
+    Ok(())
+}
+
+fn apply(input: &str, patch: &str, profile: Option<Profile>) -> std::io::Result<()> {
+    check_file(patch)?;
+
+    let patch = Patch::read(patch)?;
+    edit(input, profile, |reader, layout| {
+        patch.plan(reader, input, layout)?;
+        Ok(format!("{input}: {}", patch.describe()))
+    })
+}
+
+fn verify(input: &str, profile: Option<Profile>) -> std::io::Result<()> {
+    if Path::new(input).is_dir() {
+        return verify_directory(input, profile);
+    }
+    check_file(input)?;
+
+    verify_archive(input, profile, "")?;
+    println!("OK");
+
+    Ok(())
+}
+
+fn verify_directory(input: &str, profile: Option<Profile>) -> std::io::Result<()> {
+    let directory = Path::new(input);
+    let mut batch = Batch::default();
+
+    for file in cat_files(directory)? {
+        let path = directory.join(&file);
+        let name = file.display().to_string();
+        batch.run(&path, &name, || {
+            verify_archive(&path.to_string_lossy(), profile, &format!("{name}: "))?;
+            println!("{name}: OK");
+            Ok(())
+        });
+    }
+
+    println!("{}", batch.summary());
+    batch.result()
+}
+
+/// Prints the problems of the archive, after `prefix`.
+fn verify_archive(input: &str, profile: Option<Profile>, prefix: &str) -> std::io::Result<()> {
+    let mut reader = CatFileReader::new(input, None, profile)?;
+    let result = reader.unpack();
+
+    for problem in reader.problems() {
+        println!("{prefix}{problem}");
+    }
+    result?;
+
+    match reader.problems().len() {
+        0 => Ok(()),
+        1 => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("1 problem in '{input}'"))),
+        count => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{count} problems in '{input}'"))),
+    }
+}
+
+fn convert(input: &str, output: &str, format: Compression) -> std::io::Result<()> {
+    check_file(input)?;
+
+    let extension = |path: &str| Path::new(path).extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+    match (extension(input).as_deref(), extension(output).as_deref()) {
+        (Some("dds"), Some("png")) => {
+            let dds = Dds::read(File::open(input)?)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{input}: {e}")))?;
+            let image = image_from_dds(&dds, 0)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{input}: {e}")))?;
+            image.save(output)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{output}: {e}")))?;
+        },
+        (Some("png"), Some("dds")) => {
+            let image = image::open(input)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{input}: {e}")))?
+                .to_rgba8();
+            let dds = texture::dds_from_image(&image, &TextureFormat::D3DFormat(format, format.pixel_format()))
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{input}: {e}")))?;
+            dds.write(&mut BufWriter::new(File::create(output)?))
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{output}: {e}")))?;
+        },
+        _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Can't convert '{input}' to '{output}', only DDS to PNG and PNG to DDS are supported."))),
+    }
+    println!("OK");
+
+    Ok(())
+}
+
+fn check_file(input: &str) -> std::io::Result<()> {
+    let path = Path::new(input);
     if !path.exists() {
-        eprintln!("'{}' does not exist.", args.input);
-        return;
-    }
-
-    if let Some(output) = args.pack {
-        if !path.is_dir() {
-            eprintln!("'{}' is not a directory.", args.input);
-            return;
-        }
-
-        let mut writer = CatFileWriter::new(args.input, &output);
-        if let Err(e) = writer.pack() {
-            eprintln!("{e}");
-        } else {
-            println!("OK");
-        }
+        Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("'{input}' does not exist.")))
+    } else if !path.is_file() {
+        Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("'{input}' is not a file.")))
     } else {
-        if !path.is_file() {
-            eprintln!("'{}' is not a file.", args.input);
-            return;
-        }
-
-        let mut reader = CatFileReader::new(&args.input, args.extract.as_deref().map(str::to_string));
-        match reader.unpack() {
-            Ok(obj) => {
-                if let Some(output) = args.extract {
-                    std::fs::create_dir_all(format!("{output}")).unwrap();
-                    let writer = File::create(format!("{output}/metadata.json")).unwrap();
-                    serde_json::to_writer_pretty(writer, &obj).unwrap();
-                }
-                println!("OK");
-            },
-            Err(e) => {
-                eprintln!("{e}");
-            },
-        };
+        Ok(())
     }
 }
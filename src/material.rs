@@ -0,0 +1,69 @@
+use serde::*;
+
+/// Content of the `mtl.csv` found next to the models of format 8 containers.
+///
+/// Each line is a material: its name followed by its parameters.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MaterialTable {
+    pub materials: Vec<Material>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub values: Vec<MaterialValue>,
+}
+
+/// Parameter of a material, printed in the JSON listing as a number or a string.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum MaterialValue {
+    Int(i64),
+    Float(f32),
+    Text(String),
+}
+
+impl MaterialTable {
+    pub fn parse(csv: &[u8]) -> std::io::Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(csv);
+
+        let mut materials = vec![];
+        for record in reader.records() {
+            let record = record.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+
+            let mut cells: Vec<&str> = record.iter().collect();
+            // lines end with a comma, like the lists of names
+            if cells.last().is_some_and(|cell| cell.is_empty()) {
+                cells.pop();
+            }
+            let Some((name, values)) = cells.split_first() else {
+                continue;
+            };
+
+            materials.push(Material {
+                name: name.to_string(),
+                values: values.iter().map(|cell| MaterialValue::parse(cell)).collect(),
+            });
+        }
+
+        Ok(Self {
+            materials,
+        })
+    }
+}
+
+impl MaterialValue {
+    fn parse(cell: &str) -> Self {
+        if let Ok(value) = cell.parse::<i64>() {
+            MaterialValue::Int(value)
+        } else if let Ok(value) = cell.parse::<f32>() {
+            MaterialValue::Float(value)
+        } else {
+            MaterialValue::Text(cell.to_string())
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use serde::*;
+use crate::endian::Endian;
+use crate::strings::StringEncoding;
+
+/// Table of strings used for the dialogues and the menus.
+///
+/// Layout, in the byte order of its container:
+/// - 4 bytes: number of strings
+/// - 4 * strings bytes: offsets of the strings (relative to the start of the table)
+/// - null-terminated strings (UTF-8 or Shift-JIS), each one padded with zeros to `alignment`
+/// - 0x00 until 4 bytes aligned
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageTable {
+    pub format: MessageFormat,
+    pub messages: Vec<String>,
+}
+
+/// How a message table is written, so it can be written back the same way.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MessageFormat {
+    pub alignment: u32,
+    #[serde(default)]
+    pub encoding: StringEncoding,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Messages {
+    pub filename: String,
+    #[serde(flatten)]
+    pub format: MessageFormat,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct MessageRecord {
+    id: usize,
+    original: String,
+    translation: String,
+}
+
+impl MessageTable {
+    /// Only returns a table if writing it back gives the exact same bytes.
+    pub fn read(buffer: &[u8], endian: Endian) -> Option<Self> {
+        if buffer.len() < 8 {
+            return None;
+        }
+
+        let count = endian.u32_at(buffer) as usize;
+        if count == 0 || 4 + count * 4 > buffer.len() {
+            return None;
+        }
+
+        let mut strings = vec![];
+        for id in 0..count {
+            let offset = endian.u32_at(&buffer[4 + id * 4..]) as usize;
+            if offset < 4 + count * 4 || offset >= buffer.len() {
+                return None;
+            }
+
+            let length = buffer[offset..].iter().position(|b| *b == 0)?;
+            strings.push(&buffer[offset..offset + length]);
+        }
+
+        // Shift-JIS if some strings aren't UTF-8
+        let (encoding, messages) = [StringEncoding::Utf8, StringEncoding::ShiftJis].into_iter().find_map(|encoding| {
+            let messages: Option<Vec<_>> = strings.iter().map(|string| encoding.decode(string)).collect();
+            Some((encoding, messages?))
+        })?;
+
+        [1, 4].into_iter()
+            .map(|alignment| Self { format: MessageFormat { alignment, encoding }, messages: messages.clone() })
+            .find(|table| table.write(endian).is_ok_and(|written| written == buffer))
+    }
+
+    pub fn write(&self, endian: Endian) -> std::io::Result<Vec<u8>> {
+        let count = self.messages.len();
+        let mut buffer = vec![0u8; 4 + count * 4];
+        buffer[..4].copy_from_slice(&endian.to_bytes(count as u32));
+
+        for (id, message) in self.messages.iter().enumerate() {
+            let offset = buffer.len() as u32;
+            buffer[4 + id * 4..8 + id * 4].copy_from_slice(&endian.to_bytes(offset));
+
+            let Some(bytes) = self.format.encoding.encode(message) else {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Message {id} can't be encoded in {:?}: {message}", self.format.encoding)));
+            };
+            buffer.extend_from_slice(&bytes);
+            buffer.push(0);
+            align(&mut buffer, self.format.alignment as usize);
+        }
+        align(&mut buffer, 4);
+
+        Ok(buffer)
+    }
+
+    pub fn to_csv(&self) -> std::io::Result<Vec<u8>> {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        for (id, message) in self.messages.iter().enumerate() {
+            writer.serialize(MessageRecord {
+                id,
+                original: message.clone(),
+                translation: String::new(),
+            })?;
+        }
+
+        writer.into_inner().map_err(|e| e.into_error())
+    }
+
+    /// The translation is used when there is one, otherwise the original string is kept.
+    pub fn from_csv(csv: &[u8], format: MessageFormat) -> std::io::Result<Self> {
+        let mut reader = csv::Reader::from_reader(csv);
+        let mut messages = vec![];
+
+        for (id, record) in reader.deserialize::<MessageRecord>().enumerate() {
+            let record = record?;
+            if record.id != id {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Message {} is missing or out of order", id)));
+            }
+
+            if record.translation.is_empty() {
+                messages.push(record.original);
+            } else {
+                messages.push(record.translation);
+            }
+        }
+
+        Ok(Self {
+            format,
+            messages,
+        })
+    }
+}
+
+fn align(buffer: &mut Vec<u8>, alignment: usize) {
+    while !buffer.len().is_multiple_of(alignment) {
+        buffer.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(alignment: u32) -> MessageTable {
+        MessageTable {
+            format: MessageFormat { alignment, encoding: StringEncoding::Utf8 },
+            messages: vec!["Hello".into(), "Bye, then".into(), "OK".into()],
+        }
+    }
+
+    #[test]
+    fn write_then_read() {
+        for endian in [Endian::Little, Endian::Big] {
+            for alignment in [1, 4] {
+                let table = table(alignment);
+                assert_eq!(MessageTable::read(&table.write(endian).unwrap(), endian), Some(table));
+            }
+        }
+    }
+
+    #[test]
+    fn longer_translation_moves_the_next_messages() {
+        let table = table(4);
+        let csv = String::from_utf8(table.to_csv().unwrap()).unwrap();
+        let csv = csv.replacen("0,Hello,", "0,Hello,\"Hello, and welcome to the ranch\"", 1);
+        let translated = MessageTable::from_csv(csv.as_bytes(), table.format).unwrap();
+        assert_eq!(translated.messages, ["Hello, and welcome to the ranch", "Bye, then", "OK"]);
+
+        let buffer = translated.write(Endian::Big).unwrap();
+        assert!(buffer.len() > table.write(Endian::Big).unwrap().len());
+        assert_eq!(MessageTable::read(&buffer, Endian::Big), Some(translated));
+    }
+
+    #[test]
+    fn missing_message_is_an_error() {
+        let csv = "id,original,translation\n0,Hello,\n2,OK,\n";
+        assert!(MessageTable::from_csv(csv.as_bytes(), table(4).format).is_err());
+    }
+}
@@ -0,0 +1,317 @@
+use std::io::*;
+use crate::endian::Endian;
+
+/// Navigation mesh stored in format 5 containers with three children.
+///
+/// Only their place is known: the two children after the names of those containers. The layout below is
+/// assumed and hasn't been checked against the files of the games, it's only used for the children that
+/// are read entirely with it (so they are written back the same), the others are extracted as they are.
+///
+/// Layout, in the byte order of the container:
+/// - 4 bytes: number of vertices
+/// - 4 bytes: number of polygons
+/// - 12 * vertices bytes: positions (x, y, z as f32)
+/// - for each polygon:
+///     - 4 bytes: number of vertices in the polygon
+///     - 4 * n bytes: vertex indices
+///     - 4 * n bytes: index of the polygon sharing the edge starting at the same vertex (-1 if none)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Navmesh {
+    pub vertices: Vec<[f32; 3]>,
+    pub polygons: Vec<Polygon>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    pub vertices: Vec<u32>,
+    pub neighbours: Vec<i32>,
+}
+
+/// Comment written at the start of the OBJ, with the numbers of vertices and polygons.
+const HEADER: &str = "# navmesh:";
+/// Comment written after each face of the OBJ: its index, the face as it was extracted, then its neighbours.
+const ADJACENCY: &str = "# adjacency";
+
+impl Navmesh {
+    pub fn read(buffer: &[u8], endian: Endian) -> std::io::Result<Self> {
+        let mut cursor = Cursor::new(buffer);
+
+        let vertex_count = endian.read_u32(&mut cursor)? as u64;
+        let polygon_count = endian.read_u32(&mut cursor)? as u64;
+        if 8 + vertex_count * 12 + polygon_count * 4 > buffer.len() as u64 {
+            return Err(invalid_data("Navmesh counts don't fit in the buffer"));
+        }
+
+        let mut vertices = vec![];
+        for _ in 0..vertex_count {
+            vertices.push([
+                f32::from_bits(endian.read_u32(&mut cursor)?),
+                f32::from_bits(endian.read_u32(&mut cursor)?),
+                f32::from_bits(endian.read_u32(&mut cursor)?),
+            ]);
+        }
+
+        let mut polygons = vec![];
+        for _ in 0..polygon_count {
+            let count = endian.read_u32(&mut cursor)? as usize;
+            if count < 3 || count * 8 > buffer.len() {
+                return Err(invalid_data("Invalid navmesh polygon"));
+            }
+
+            let mut indices = vec![];
+            for _ in 0..count {
+                let index = endian.read_u32(&mut cursor)?;
+                if index as u64 >= vertex_count {
+                    return Err(invalid_data("Navmesh vertex index out of range"));
+                }
+                indices.push(index);
+            }
+
+            let mut neighbours = vec![];
+            for _ in 0..count {
+                let neighbour = endian.read_u32(&mut cursor)? as i32;
+                if neighbour < -1 || neighbour as i64 >= polygon_count as i64 {
+                    return Err(invalid_data("Navmesh neighbour out of range"));
+                }
+                neighbours.push(neighbour);
+            }
+
+            polygons.push(Polygon {
+                vertices: indices,
+                neighbours,
+            });
+        }
+
+        if cursor.position() != buffer.len() as u64 {
+            return Err(invalid_data("Trailing data after navmesh"));
+        }
+
+        Ok(Self {
+            vertices,
+            polygons,
+        })
+    }
+
+    pub fn write<W: Write>(&self, output: &mut W, endian: Endian) -> std::io::Result<()> {
+        endian.write_u32(output, self.vertices.len() as u32)?;
+        endian.write_u32(output, self.polygons.len() as u32)?;
+        for vertex in &self.vertices {
+            for coordinate in vertex {
+                endian.write_u32(output, coordinate.to_bits())?;
+            }
+        }
+        for polygon in &self.polygons {
+            endian.write_u32(output, polygon.vertices.len() as u32)?;
+            for index in &polygon.vertices {
+                endian.write_u32(output, *index)?;
+            }
+            for neighbour in &polygon.neighbours {
+                endian.write_u32(output, *neighbour as u32)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Each face is followed by a comment with its neighbours, so they are kept when packing.
+    pub fn to_obj<W: Write>(&self, output: &mut W) -> std::io::Result<()> {
+        writeln!(output, "{HEADER} {} vertices, {} polygons", self.vertices.len(), self.polygons.len())?;
+        for [x, y, z] in &self.vertices {
+            writeln!(output, "v {x} {y} {z}")?;
+        }
+        for (id, polygon) in self.polygons.iter().enumerate() {
+            let indices: Vec<_> = polygon.vertices.iter().map(|i| (i + 1).to_string()).collect();
+            let neighbours: Vec<_> = polygon.neighbours.iter().map(i32::to_string).collect();
+            writeln!(output, "f {}", indices.join(" "))?;
+            writeln!(output, "{ADJACENCY} {id}: {} -> {}", indices.join(" "), neighbours.join(" "))?;
+        }
+
+        Ok(())
+    }
+
+    /// Only `v` and `f` lines are used, with the neighbours written by `to_obj`. If the faces changed,
+    /// the neighbours are rebuilt from the shared edges.
+    pub fn from_obj(obj: &str) -> std::io::Result<Self> {
+        let mut vertices = vec![];
+        let mut polygons = vec![];
+        // the neighbours of each face, if its adjacency comment follows it and it's still the same face
+        let mut kept: Vec<Option<Vec<i32>>> = vec![];
+        // the number of polygons when extracted
+        let mut polygon_count = None;
+
+        for (line_number, line) in obj.lines().enumerate() {
+            if let Some(header) = line.strip_prefix(HEADER) {
+                polygon_count = header.split(',').nth(1).and_then(|polygons| polygons.trim().strip_suffix(" polygons")?.parse::<usize>().ok());
+                continue;
+            }
+            if let Some(adjacency) = line.strip_prefix(ADJACENCY) {
+                if let (Some(polygon), Some(neighbours @ None)) = (polygons.last(), kept.last_mut()) {
+                    *neighbours = parse_adjacency(adjacency, polygons.len() - 1, polygon);
+                }
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("v") => {
+                    let coords: Vec<f32> = parts.take(3).map(|p| p.parse::<f32>()).collect::<std::result::Result<_, _>>()
+                        .map_err(|_| invalid_data(&format!("Invalid vertex on line {}", line_number + 1)))?;
+                    if coords.len() != 3 {
+                        return Err(invalid_data(&format!("Invalid vertex on line {}", line_number + 1)));
+                    }
+                    vertices.push([coords[0], coords[1], coords[2]]);
+                },
+                Some("f") => {
+                    let mut indices = vec![];
+                    for part in parts {
+                        // "f 1/2/3" only keeps the position index
+                        let index = part.split('/').next().unwrap().parse::<i64>()
+                            .map_err(|_| invalid_data(&format!("Invalid face on line {}", line_number + 1)))?;
+                        let index = if index < 0 { vertices.len() as i64 + index } else { index - 1 };
+                        if index < 0 || index as usize >= vertices.len() {
+                            return Err(invalid_data(&format!("Face index out of range on line {}", line_number + 1)));
+                        }
+                        indices.push(index as u32);
+                    }
+                    if indices.len() < 3 {
+                        return Err(invalid_data(&format!("Degenerate face on line {}", line_number + 1)));
+                    }
+                    polygons.push(Polygon {
+                        neighbours: vec![-1; indices.len()],
+                        vertices: indices,
+                    });
+                    kept.push(None);
+                },
+                _ => {},
+            }
+        }
+
+        let mut navmesh = Self {
+            vertices,
+            polygons,
+        };
+
+        // the neighbours refer to the faces by index, they are only kept if none was added, removed, moved or changed
+        let kept: Option<Vec<_>> = kept.into_iter().collect();
+        let in_range = |neighbours: &Vec<i32>| neighbours.iter().all(|neighbour| (-1..navmesh.polygons.len() as i32).contains(neighbour));
+        match kept {
+            Some(kept) if polygon_count == Some(kept.len()) && kept.iter().all(in_range) => {
+                for (polygon, neighbours) in navmesh.polygons.iter_mut().zip(kept) {
+                    polygon.neighbours = neighbours;
+                }
+            },
+            _ => navmesh.compute_adjacency(),
+        }
+
+        Ok(navmesh)
+    }
+
+    fn compute_adjacency(&mut self) {
+        let mut edges = std::collections::HashMap::new();
+        for (id, polygon) in self.polygons.iter().enumerate() {
+            for (edge, (a, b)) in polygon_edges(polygon).enumerate() {
+                edges.insert((a, b), (id, edge));
+            }
+        }
+
+        for id in 0..self.polygons.len() {
+            let neighbours: Vec<_> = polygon_edges(&self.polygons[id])
+                .map(|(a, b)| edges.get(&(b, a)).map(|(other, _)| *other as i32).unwrap_or(-1))
+                .collect();
+            self.polygons[id].neighbours = neighbours;
+        }
+    }
+}
+
+/// The neighbours of an adjacency comment (`2: 1 2 3 -> -1 4 -1`), `None` if it isn't the one of `polygon` at `id`.
+fn parse_adjacency(adjacency: &str, id: usize, polygon: &Polygon) -> Option<Vec<i32>> {
+    let (index, rest) = adjacency.split_once(':')?;
+    if index.trim().parse::<usize>().ok()? != id {
+        return None;
+    }
+    let (face, neighbours) = rest.split_once("->")?;
+    let face: Vec<u32> = face.split_whitespace().map(|index| index.parse::<u32>().ok()?.checked_sub(1)).collect::<Option<_>>()?;
+    let neighbours: Vec<i32> = neighbours.split_whitespace().map(|neighbour| neighbour.parse().ok()).collect::<Option<_>>()?;
+
+    (face == polygon.vertices && neighbours.len() == face.len()).then_some(neighbours)
+}
+
+fn polygon_edges(polygon: &Polygon) -> impl Iterator<Item = (u32, u32)> + '_ {
+    let count = polygon.vertices.len();
+    (0..count).map(move |i| (polygon.vertices[i], polygon.vertices[(i + 1) % count]))
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two triangles sharing an edge, with neighbours that aren't the ones computed from the edges.
+    fn navmesh() -> Navmesh {
+        Navmesh {
+            vertices: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.0, 1.0], [0.0, 0.0, 1.0]],
+            polygons: vec![
+                Polygon { vertices: vec![0, 1, 2], neighbours: vec![-1, -1, -1] },
+                Polygon { vertices: vec![0, 2, 3], neighbours: vec![-1, -1, 0] },
+            ],
+        }
+    }
+
+    fn obj(navmesh: &Navmesh) -> String {
+        let mut obj = vec![];
+        navmesh.to_obj(&mut obj).unwrap();
+        String::from_utf8(obj).unwrap()
+    }
+
+    /// The lines of `obj` without the face at `id` and its adjacency comment.
+    fn without_face(obj: &str, id: usize) -> String {
+        let lines: Vec<_> = obj.lines().collect();
+        let face = lines.iter().position(|line| line.starts_with(&format!("{ADJACENCY} {id}:"))).unwrap() - 1;
+        lines.iter().enumerate().filter(|(index, _)| *index != face && *index != face + 1).map(|(_, line)| format!("{line}\n")).collect()
+    }
+
+    #[test]
+    fn write_then_read() {
+        for endian in [Endian::Little, Endian::Big] {
+            let mut buffer = vec![];
+            navmesh().write(&mut buffer, endian).unwrap();
+            assert_eq!(Navmesh::read(&buffer, endian).unwrap(), navmesh());
+        }
+    }
+
+    #[test]
+    fn neighbours_are_kept() {
+        assert_eq!(Navmesh::from_obj(&obj(&navmesh())).unwrap(), navmesh());
+    }
+
+    #[test]
+    fn neighbours_are_computed_when_a_face_changes() {
+        let obj = obj(&navmesh()).replace("f 1 3 4\n", "f 4 1 3\n");
+        let navmesh = Navmesh::from_obj(&obj).unwrap();
+        assert_eq!(navmesh.polygons[0].neighbours, vec![-1, -1, 1]);
+        assert_eq!(navmesh.polygons[1].neighbours, vec![-1, 0, -1]);
+    }
+
+    #[test]
+    fn neighbours_are_computed_when_a_face_is_removed() {
+        for id in 0..2 {
+            let navmesh = Navmesh::from_obj(&without_face(&obj(&navmesh()), id)).unwrap();
+            assert_eq!(navmesh.polygons.len(), 1);
+            assert_eq!(navmesh.polygons[0].neighbours, vec![-1, -1, -1]);
+        }
+    }
+
+    #[test]
+    fn neighbours_are_computed_when_faces_are_moved() {
+        let obj = obj(&navmesh());
+        let (start, faces) = obj.split_at(obj.find("f ").unwrap());
+        let (first, second) = faces.split_at(faces.find("f 1 3 4").unwrap());
+        let navmesh = Navmesh::from_obj(&format!("{start}{second}{first}")).unwrap();
+        assert_eq!(navmesh.polygons[0].vertices, vec![0, 2, 3]);
+        assert_eq!(navmesh.polygons[0].neighbours, vec![1, -1, -1]);
+        assert_eq!(navmesh.polygons[1].neighbours, vec![-1, -1, 0]);
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::io::Write;
+use serde::*;
+use crate::archive::CatFileReader;
+use crate::layout::Layout;
+use crate::tree::{human_size, Entry, Node, NodeKind};
+
+const MAGIC: &[u8; 4] = b"ASP1";
+
+/// Differences between an original archive and a modded one, written by `asuka mkpatch` in a .asp file:
+/// the magic, the size of the JSON manifest (little-endian), the manifest, then the bytes of the modded archive
+/// that aren't copied from the original.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Patch {
+    /// Size of the patched archive.
+    pub size: u64,
+    /// CRC-32 of the patched archive.
+    pub crc32: u32,
+    pub pieces: Vec<PatchPiece>,
+    #[serde(skip)]
+    data: Vec<u8>,
+}
+
+/// Part of the patched archive, in order.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PatchPiece {
+    /// Entry of the original at `path`, copied as it is if its checksum matches.
+    Entry {
+        path: String,
+        size: u64,
+        crc32: u32,
+    },
+    /// Bytes of the patch, starting at `offset` in its data.
+    Data {
+        offset: u64,
+        size: u64,
+    },
+    Zeros {
+        size: u64,
+    },
+}
+
+impl Patch {
+    /// Patch turning `original` into `modded`, which is `modded_size` bytes long. The entries of `modded` that are
+    /// the same as the entry with their path in `original` are copied, everything else is stored in the patch.
+    pub fn make(original: &CatFileReader, modded: &CatFileReader, modded_size: u64) -> std::io::Result<Self> {
+        let originals = files(original);
+        let mut copied = vec![];
+        for Entry { path, node, .. } in modded.tree().map(Node::entries).unwrap_or_default() {
+            if !is_file(node) {
+                continue;
+            }
+            if let Some(original_node) = originals.get(path.as_str()) {
+                if original.bytes(original_node.offset, original_node.size)? == modded.bytes(node.offset, node.size)? {
+                    copied.push((path, node));
+                }
+            }
+        }
+        copied.sort_by_key(|(_, node)| node.offset);
+
+        let mut patch = Self {
+            size: modded_size,
+            crc32: crc32fast::hash(modded.bytes(0, modded_size)?),
+            pieces: vec![],
+            data: vec![],
+        };
+
+        let mut position = 0;
+        for (path, node) in copied {
+            // entries inside an entry that's already copied
+            if node.offset < position {
+                continue;
+            }
+            patch.push_bytes(modded.bytes(position, node.offset - position)?);
+
+            let bytes = modded.bytes(node.offset, node.size)?;
+            patch.pieces.push(PatchPiece::Entry {
+                path,
+                size: node.size,
+                crc32: crc32fast::hash(bytes),
+            });
+            position = node.offset + node.size;
+        }
+        patch.push_bytes(modded.bytes(position, modded_size - position)?);
+
+        Ok(patch)
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+
+        let size = bytes.len() as u64;
+        if bytes.iter().all(|byte| *byte == 0) {
+            self.pieces.push(PatchPiece::Zeros { size });
+        } else {
+            self.pieces.push(PatchPiece::Data { offset: self.data.len() as u64, size });
+            self.data.extend_from_slice(bytes);
+        }
+    }
+
+    pub fn read(path: &str) -> std::io::Result<Self> {
+        let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, format!("'{path}' is not a patch"));
+
+        let buffer = std::fs::read(path)?;
+        if buffer.get(0..4) != Some(MAGIC) {
+            return Err(invalid());
+        }
+        let manifest_size = u32::from_le_bytes(buffer.get(4..8).ok_or_else(invalid)?.try_into().unwrap()) as usize;
+        let manifest = buffer.get(8..8 + manifest_size).ok_or_else(invalid)?;
+
+        let mut patch: Self = serde_json::from_slice(manifest)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{path}: {e}")))?;
+        patch.data = buffer[8 + manifest_size..].to_vec();
+
+        Ok(patch)
+    }
+
+    pub fn write<W: Write>(&self, output: &mut W) -> std::io::Result<()> {
+        let manifest = serde_json::to_vec(self)?;
+        output.write_all(MAGIC)?;
+        output.write_all(&(manifest.len() as u32).to_le_bytes())?;
+        output.write_all(&manifest)?;
+        output.write_all(&self.data)
+    }
+
+    /// Plans the patched archive from `reader`, the original at `input`. Fails before anything is written if an entry
+    /// isn't the one the patch was made from, or if the result wouldn't be the modded archive.
+    pub fn plan(&self, reader: &CatFileReader, input: &str, layout: &mut Layout) -> std::io::Result<()> {
+        let originals = files(reader);
+        let mut hasher = crc32fast::Hasher::new();
+
+        for piece in &self.pieces {
+            match piece {
+                PatchPiece::Entry { path, size, crc32 } => {
+                    let node = originals.get(path.as_str()).ok_or_else(|| {
+                        std::io::Error::new(std::io::ErrorKind::NotFound, format!("'{path}' isn't in '{input}'"))
+                    })?;
+                    let bytes = reader.bytes(node.offset, node.size)?;
+                    if node.size != *size || crc32fast::hash(bytes) != *crc32 {
+                        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("'{path}' of '{input}' isn't the one the patch was made from")));
+                    }
+                    hasher.update(bytes);
+                    layout.push_range(input.to_string(), node.offset, node.size);
+                },
+                PatchPiece::Data { offset, size } => {
+                    let bytes = self.data.get(*offset as usize..(offset + size) as usize).ok_or_else(|| {
+                        std::io::Error::new(std::io::ErrorKind::InvalidData, "The patch is truncated")
+                    })?;
+                    hasher.update(bytes);
+                    layout.push_bytes(bytes.to_vec());
+                },
+                PatchPiece::Zeros { size } => {
+                    hasher.update(&vec![0; *size as usize]);
+                    layout.push_bytes(vec![0; *size as usize]);
+                },
+            }
+        }
+
+        if layout.position() != self.size || hasher.finalize() != self.crc32 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("The patched '{input}' doesn't match the checksum of the patch")));
+        }
+
+        Ok(())
+    }
+
+    pub fn describe(&self) -> String {
+        let copied = self.pieces.iter().filter(|piece| matches!(piece, PatchPiece::Entry { .. })).count();
+        let entries = if copied == 1 { "entry" } else { "entries" };
+        format!("{} of data, {copied} {entries} copied from the original", human_size(self.data.len() as u64))
+    }
+}
+
+/// Entries of the archive that aren't containers, by path.
+fn files(reader: &CatFileReader) -> HashMap<String, &Node> {
+    reader.tree().map(Node::entries).unwrap_or_default()
+        .into_iter()
+        .filter(|entry| is_file(entry.node))
+        .map(|Entry { path, node, .. }| (path, node))
+        .collect()
+}
+
+fn is_file(node: &Node) -> bool {
+    !matches!(node.kind, NodeKind::Container { .. })
+}
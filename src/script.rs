@@ -0,0 +1,265 @@
+use std::fmt::Write as _;
+use crate::endian::Endian;
+
+/// Scripts found in format 5 containers.
+///
+/// The opcodes aren't known yet, so this isn't a disassembler: a script is dumped word by word,
+/// recovering the strings and floats it contains:
+/// - `.word 12` or `.word 0x1234ABCD`: a 32-bit word
+/// - `.float 1.5`: a 32-bit float
+/// - `.string "text"`: a null-terminated string, padded with zeros to 4 bytes
+/// - `.byte 0x12`: trailing bytes when the size isn't a multiple of 4
+///
+/// Everything after a `;` is a comment. The dump writes the offset of each item there, the offsets
+/// are checked when reading it back since the words that reference them can't be fixed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptItem {
+    Word(u32),
+    Float(f32),
+    String(String),
+    Byte(u8),
+}
+
+/// Items of a script whose words are in the byte order `endian`.
+pub fn read(buffer: &[u8], endian: Endian) -> Vec<ScriptItem> {
+    let mut items = vec![];
+    let mut pos = 0;
+
+    while pos + 4 <= buffer.len() {
+        if let Some((string, size)) = string_at(buffer, pos) {
+            items.push(ScriptItem::String(string));
+            pos += size;
+            continue;
+        }
+
+        let word = endian.u32_at(&buffer[pos..]);
+        if is_float(word) {
+            items.push(ScriptItem::Float(f32::from_bits(word)));
+        } else {
+            items.push(ScriptItem::Word(word));
+        }
+        pos += 4;
+    }
+
+    for byte in &buffer[pos..] {
+        items.push(ScriptItem::Byte(*byte));
+    }
+
+    items
+}
+
+pub fn write(items: &[ScriptItem], endian: Endian) -> Vec<u8> {
+    let mut buffer = vec![];
+
+    for item in items {
+        match item {
+            ScriptItem::Word(word) => buffer.extend_from_slice(&endian.to_bytes(*word)),
+            ScriptItem::Float(float) => buffer.extend_from_slice(&endian.to_bytes(float.to_bits())),
+            ScriptItem::String(string) => {
+                buffer.extend_from_slice(string.as_bytes());
+                buffer.push(0);
+                while buffer.len() % 4 != 0 {
+                    buffer.push(0);
+                }
+            },
+            ScriptItem::Byte(byte) => buffer.push(*byte),
+        }
+    }
+
+    buffer
+}
+
+pub fn to_text(items: &[ScriptItem]) -> String {
+    let mut text = String::new();
+    let mut offset = 0;
+
+    for item in items {
+        let line = match item {
+            ScriptItem::Word(word) if *word < 0x10000 => format!(".word {word}"),
+            ScriptItem::Word(word) => format!(".word {word:#010X}"),
+            ScriptItem::Float(float) => format!(".float {float:?}"),
+            ScriptItem::String(string) => format!(".string \"{}\"", escape(string)),
+            ScriptItem::Byte(byte) => format!(".byte {byte:#04X}"),
+        };
+        writeln!(text, "{line:<40} ; {offset:#06X}").unwrap();
+        offset += item.size();
+    }
+
+    text
+}
+
+/// Fails if an item isn't at the offset written in its comment: the script could reference the items after it
+/// (strings can only be changed if their size with the padding stays the same).
+pub fn from_text(text: &str) -> std::io::Result<Vec<ScriptItem>> {
+    let mut items = vec![];
+    let mut offset = 0;
+
+    for (line_number, line) in text.lines().enumerate() {
+        let error = || std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid script on line {}: {line}", line_number + 1));
+
+        let (content, comment) = split_comment(line);
+        let expected = comment.trim().strip_prefix("0x").and_then(|hex| usize::from_str_radix(hex, 16).ok());
+        let line = content.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (directive, value) = line.split_once(char::is_whitespace).ok_or_else(error)?;
+        let value = value.trim();
+        let item = match directive {
+            ".word" => ScriptItem::Word(parse_int(value).ok_or_else(error)? as u32),
+            ".float" => ScriptItem::Float(value.parse().map_err(|_| error())?),
+            ".string" => ScriptItem::String(unescape(value).ok_or_else(error)?),
+            ".byte" => ScriptItem::Byte(u8::try_from(parse_int(value).ok_or_else(error)?).map_err(|_| error())?),
+            _ => return Err(error()),
+        };
+
+        if let Some(expected) = expected.filter(|expected| *expected != offset) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!(
+                "Line {} was at {expected:#06X} and is now at {offset:#06X}, the sizes of the strings before it changed", line_number + 1,
+            )));
+        }
+        offset += item.size();
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+impl ScriptItem {
+    /// Size in the script, with the padding of the strings.
+    fn size(&self) -> usize {
+        match self {
+            ScriptItem::String(string) => (string.len() + 4) & !3,
+            ScriptItem::Byte(_) => 1,
+            _ => 4,
+        }
+    }
+}
+
+/// A string starts at a word, has at least 3 printable characters and is padded with zeros.
+fn string_at(buffer: &[u8], pos: usize) -> Option<(String, usize)> {
+    let length = buffer[pos..].iter().position(|b| *b == 0)?;
+    if length < 3 {
+        return None;
+    }
+
+    let string = std::str::from_utf8(&buffer[pos..pos + length]).ok()?;
+    if string.chars().any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t')) {
+        return None;
+    }
+
+    let size = (length + 4) & !3;
+    if pos + size > buffer.len() || buffer[pos + length..pos + size].iter().any(|b| *b != 0) {
+        return None;
+    }
+
+    Some((string.to_string(), size))
+}
+
+/// Only keeps the floats that look like something a designer typed.
+fn is_float(word: u32) -> bool {
+    let float = f32::from_bits(word);
+    if !float.is_normal() {
+        return false;
+    }
+
+    let abs = float.abs();
+    (1e-3..1e6).contains(&abs) && format!("{float:?}").len() <= 10
+}
+
+fn parse_int(value: &str) -> Option<i64> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value),
+    };
+    let value = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => value.parse::<i64>().ok()?,
+    };
+    let value = if negative { -value } else { value };
+
+    (i32::MIN as i64..=u32::MAX as i64).contains(&value).then_some(value & 0xFFFFFFFF)
+}
+
+/// The line before the `;` and the comment after it.
+fn split_comment(line: &str) -> (&str, &str) {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => return (&line[..i], &line[i + 1..]),
+            _ => {},
+        }
+    }
+    (line, "")
+}
+
+fn escape(string: &str) -> String {
+    string.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\r', "\\r").replace('\t', "\\t")
+}
+
+fn unescape(value: &str) -> Option<String> {
+    let value = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut string = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => string.push('\n'),
+            'r' => string.push('\r'),
+            't' => string.push('\t'),
+            c @ ('\\' | '"') => string.push(c),
+            _ => return None,
+        }
+    }
+
+    Some(string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items() -> Vec<ScriptItem> {
+        vec![
+            ScriptItem::Word(3),
+            ScriptItem::Word(0x1234ABCD),
+            ScriptItem::Float(1.5),
+            ScriptItem::String("say \"hi\"; then\tleave\n".into()),
+            ScriptItem::Word(0),
+            ScriptItem::Byte(0x12),
+            ScriptItem::Byte(0xFF),
+        ]
+    }
+
+    #[test]
+    fn dump_then_read() {
+        for endian in [Endian::Little, Endian::Big] {
+            let buffer = write(&items(), endian);
+            let read = read(&buffer, endian);
+            assert_eq!(read, items());
+            assert_eq!(write(&from_text(&to_text(&read)).unwrap(), endian), buffer);
+        }
+    }
+
+    #[test]
+    fn string_of_the_same_size_can_be_changed() {
+        let text = to_text(&items()).replace("say \\\"hi\\\"; then", "say \\\"yo\\\"; then!!");
+        let items = from_text(&text).unwrap();
+        assert_eq!(items[3], ScriptItem::String("say \"yo\"; then!!\tleave\n".into()));
+    }
+
+    #[test]
+    fn longer_string_is_an_error() {
+        let text = to_text(&items()).replace("leave", "leave now");
+        assert!(from_text(&text).is_err());
+    }
+}
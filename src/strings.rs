@@ -0,0 +1,176 @@
+use encoding_rs::SHIFT_JIS;
+use serde::*;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum StringEncoding {
+    #[default]
+    Utf8,
+    ShiftJis,
+}
+
+impl StringEncoding {
+    /// `None` if `bytes` aren't valid in this encoding.
+    pub fn decode(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            StringEncoding::Utf8 => std::str::from_utf8(bytes).ok().map(str::to_string),
+            StringEncoding::ShiftJis => SHIFT_JIS.decode_without_bom_handling_and_without_replacement(bytes).map(|text| text.into_owned()),
+        }
+    }
+
+    /// `None` if `text` has characters that can't be encoded.
+    pub fn encode(self, text: &str) -> Option<Vec<u8>> {
+        match self {
+            StringEncoding::Utf8 => Some(text.as_bytes().to_vec()),
+            StringEncoding::ShiftJis => {
+                let (bytes, _, unmappable) = SHIFT_JIS.encode(text);
+                (!unmappable).then(|| bytes.into_owned())
+            },
+        }
+    }
+}
+
+/// How the names of a string table are written, so they can be written back the same way.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StringTableFormat {
+    pub encoding: StringEncoding,
+    pub separator: String,
+    /// If the last name is also followed by the separator.
+    pub trailing_separator: bool,
+    /// Number of `\0` after the last name.
+    pub padding: usize,
+    /// Names as they are between the separators, if some have spaces or commas around them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub raw_names: Vec<String>,
+}
+
+impl Default for StringTableFormat {
+    fn default() -> Self {
+        Self {
+            encoding: StringEncoding::Utf8,
+            separator: ",\r\n".into(),
+            trailing_separator: true,
+            padding: 0,
+            raw_names: vec![],
+        }
+    }
+}
+
+const SEPARATORS: [&str; 6] = [",\r\n", ",\n", "\r\n", "\n", ",", "\0"];
+
+/// Characters around the names that aren't part of them.
+fn is_padding(c: char) -> bool {
+    c.is_whitespace() || c == ',' || c == '\0'
+}
+
+#[derive(Debug, Clone)]
+pub struct StringTable {
+    pub names: Vec<String>,
+    pub format: StringTableFormat,
+}
+
+impl StringTable {
+    /// Only returns a table if writing it back gives the exact same bytes.
+    pub fn read(buffer: &[u8]) -> Option<Self> {
+        let padding = buffer.iter().rev().take_while(|b| **b == 0).count();
+        let content = &buffer[..buffer.len() - padding];
+
+        let (text, encoding) = match std::str::from_utf8(content) {
+            Ok(text) => (text.to_string(), StringEncoding::Utf8),
+            Err(_) => {
+                let text = SHIFT_JIS.decode_without_bom_handling_and_without_replacement(content)?;
+                (text.into_owned(), StringEncoding::ShiftJis)
+            },
+        };
+
+        // the separator is between every name, so it's the one found the most,
+        // the longest one if several are found as often (`,\r\n` rather than `\r\n`)
+        let separator = SEPARATORS.iter()
+            .map(|separator| (text.matches(*separator).count(), separator.len(), *separator))
+            .filter(|(count, _, _)| *count > 0)
+            .max_by_key(|(count, length, _)| (*count, *length))
+            .map_or(SEPARATORS[0], |(_, _, separator)| separator);
+        let trailing_separator = text.ends_with(separator);
+        let text = text.strip_suffix(separator).unwrap_or(&text);
+        let raw_names: Vec<String> = if text.is_empty() {
+            vec![]
+        } else {
+            text.split(separator).map(str::to_string).collect()
+        };
+        let names: Vec<String> = raw_names.iter().map(|name| name.trim_matches(is_padding).to_string()).collect();
+
+        let table = Self {
+            format: StringTableFormat {
+                encoding,
+                separator: separator.to_string(),
+                trailing_separator,
+                padding,
+                raw_names: if raw_names == names { vec![] } else { raw_names },
+            },
+            names,
+        };
+
+        (table.write().ok()? == buffer).then_some(table)
+    }
+
+    /// Removes a name, and the way it was written.
+    pub fn remove(&mut self, index: usize) {
+        self.names.remove(index);
+        if index < self.format.raw_names.len() {
+            self.format.raw_names.remove(index);
+        }
+    }
+
+    /// The names are written as they were read, unless they were changed.
+    pub fn write(&self) -> std::io::Result<Vec<u8>> {
+        let names: Vec<&str> = self.names.iter().enumerate().map(|(id, name)| match self.format.raw_names.get(id) {
+            Some(raw) if raw.trim_matches(is_padding) == name => raw.as_str(),
+            _ => name.as_str(),
+        }).collect();
+        let mut text = names.join(&self.format.separator);
+        if self.format.trailing_separator {
+            text.push_str(&self.format.separator);
+        }
+
+        let Some(mut buffer) = self.format.encoding.encode(&text) else {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Names can't be encoded in Shift-JIS: {:?}", self.names)));
+        };
+        buffer.resize(buffer.len() + self.format.padding, 0);
+
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixed_separators_are_written_back() {
+        let buffer = b"tex_face,\r\ntex_body\nbone ,\r\n\0\0";
+        let mut table = StringTable::read(buffer).unwrap();
+        assert_eq!(table.names, ["tex_face", "tex_body", "bone"]);
+        assert_eq!(table.format.separator, "\n");
+        assert_eq!(table.format.padding, 2);
+        assert_eq!(table.write().unwrap(), buffer);
+
+        // a changed name loses what was around it, the others keep it
+        table.names[0] = "tex_head".into();
+        assert_eq!(table.write().unwrap(), b"tex_head\ntex_body\nbone ,\r\n\0\0");
+    }
+
+    #[test]
+    fn shift_jis_names_are_written_back() {
+        let (buffer, _, _) = SHIFT_JIS.encode("顔,\r\n体,\r\n");
+        let buffer = [&buffer[..], &[0]].concat();
+        let mut table = StringTable::read(&buffer).unwrap();
+        assert_eq!(table.format.encoding, StringEncoding::ShiftJis);
+        assert_eq!(table.names, ["顔", "体"]);
+        assert_eq!(table.write().unwrap(), buffer);
+
+        table.names[1] = "腕".into();
+        assert_eq!(StringTable::read(&table.write().unwrap()).unwrap().names, ["顔", "腕"]);
+
+        table.names[1] = "🙂".into();
+        assert!(table.write().is_err());
+    }
+}
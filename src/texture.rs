@@ -2,6 +2,8 @@ use crate::DxgiFormat;
 use crate::Dds;
 use crate::D3DFormat;
 use serde::*;
+use clap_derive::ValueEnum;
+use crate::magic::FileType;
 use image_dds::*;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -11,14 +13,26 @@ pub struct Texture {
     pub filename: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, ValueEnum)]
 pub enum Compression {
     Dxt1,
     Dxt3,
     Dxt5,
+    #[value(name = "a8r8g8b8")]
     A8R8G8B8,
 }
 
+impl Compression {
+    /// Pixel format of a new texture, when there is no original to copy it from.
+    pub fn pixel_format(self) -> PixelFormat {
+        match self {
+            Compression::Dxt1 => PixelFormat::BC1RgbaUnorm,
+            Compression::Dxt3 | Compression::Dxt5 => PixelFormat::BC3RgbaUnorm,
+            Compression::A8R8G8B8 => PixelFormat::Bgra8Unorm,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum PixelFormat {
     Bgra8Unorm,
@@ -36,15 +50,17 @@ pub enum PixelFormat {
 pub enum TextureFormat {
     D3DFormat(Compression, PixelFormat),
     DxgiFormat(PixelFormat),
+    /// Textures that can't be converted (GNF, GXT), extracted and packed as they are.
+    Raw(FileType),
 }
 
-fn d3d_to_compression(format: &D3DFormat) -> Compression {
+fn d3d_to_compression(format: &D3DFormat) -> Option<Compression> {
     match format {
-        D3DFormat::DXT1 => Compression::Dxt1,
-        D3DFormat::DXT3 => Compression::Dxt3,
-        D3DFormat::DXT5 => Compression::Dxt5,
-        D3DFormat::A8R8G8B8 => Compression::A8R8G8B8,
-        _ => panic!("Unhandled format: {:?}", format),
+        D3DFormat::DXT1 => Some(Compression::Dxt1),
+        D3DFormat::DXT3 => Some(Compression::Dxt3),
+        D3DFormat::DXT5 => Some(Compression::Dxt5),
+        D3DFormat::A8R8G8B8 => Some(Compression::A8R8G8B8),
+        _ => None,
     }
 }
 
@@ -57,13 +73,13 @@ fn compression_to_d3d(format: &Compression) -> D3DFormat {
     }
 }
 
-fn image_to_pixel_format(image: &ImageFormat) -> PixelFormat {
+fn image_to_pixel_format(image: &ImageFormat) -> Option<PixelFormat> {
     match image {
-        ImageFormat::BC1RgbaUnorm => PixelFormat::BC1RgbaUnorm,
-        ImageFormat::BC2RgbaUnorm => PixelFormat::BC2RgbaUnorm,
-        ImageFormat::BC3RgbaUnorm => PixelFormat::BC3RgbaUnorm,
-        ImageFormat::Bgra8Unorm => PixelFormat::Bgra8Unorm,
-        _ => panic!("Unsupported image format: {:?}", image),
+        ImageFormat::BC1RgbaUnorm => Some(PixelFormat::BC1RgbaUnorm),
+        ImageFormat::BC2RgbaUnorm => Some(PixelFormat::BC2RgbaUnorm),
+        ImageFormat::BC3RgbaUnorm => Some(PixelFormat::BC3RgbaUnorm),
+        ImageFormat::Bgra8Unorm => Some(PixelFormat::Bgra8Unorm),
+        _ => None,
     }
 }
 
@@ -81,22 +97,20 @@ fn pixel_to_image_format(pixels: &PixelFormat) -> ImageFormat {
     }
 }
 
-fn dxgi_to_texture_format(format: DxgiFormat) -> PixelFormat {
+fn dxgi_to_texture_format(format: DxgiFormat) -> Option<PixelFormat> {
     match format {
-        DxgiFormat::BC1_UNorm_sRGB => PixelFormat::BC1RgbaUnormSrgb,
-        DxgiFormat::BC3_UNorm_sRGB => PixelFormat::BC3RgbaUnormSrgb,
-        DxgiFormat::BC7_UNorm => PixelFormat::BC7RgbaUnorm,
-        DxgiFormat::BC7_UNorm_sRGB => PixelFormat::BC7RgbaUnormSrgb,
-        DxgiFormat::BC6H_UF16 => PixelFormat::BC6hRgbUfloat,
-        _ => panic!("Unsupported DXGI format: {:?}", format),
+        DxgiFormat::BC1_UNorm_sRGB => Some(PixelFormat::BC1RgbaUnormSrgb),
+        DxgiFormat::BC3_UNorm_sRGB => Some(PixelFormat::BC3RgbaUnormSrgb),
+        DxgiFormat::BC7_UNorm => Some(PixelFormat::BC7RgbaUnorm),
+        DxgiFormat::BC7_UNorm_sRGB => Some(PixelFormat::BC7RgbaUnormSrgb),
+        DxgiFormat::BC6H_UF16 => Some(PixelFormat::BC6hRgbUfloat),
+        _ => None,
     }
 }
 
 #[allow(unused)]
 fn texture_to_dxgi_format(format: TextureFormat) -> DxgiFormat {
-    match format {
-        _ => panic!("Unsupported Texture format: {:?}", format),
-    }
+    panic!("Unsupported Texture format: {:?}", format)
 }
 
 pub fn dds_from_image(
@@ -110,23 +124,77 @@ pub fn dds_from_image(
         TextureFormat::DxgiFormat(_pixelformat) => {
             todo!()
         },
+        TextureFormat::Raw(file_type) => panic!("{:?} textures are packed as they are", file_type),
+    }
+}
+
+/// DDS file encoded from the PNG at `filename`.
+pub fn dds_from_png(filename: &str, format: &TextureFormat) -> std::io::Result<Vec<u8>> {
+    use image::buffer::ConvertBuffer;
+
+    let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{filename}: {e}"));
+
+    let img = image::io::Reader::open(filename)?.decode().map_err(|e| invalid(e.to_string()))?;
+    let img = match img {
+        image::DynamicImage::ImageRgba8(image) => image,
+        image::DynamicImage::ImageRgb8(image) => {
+            let rgba_image: image::RgbaImage = image.convert();
+            rgba_image
+        },
+        _ => return Err(invalid(format!("not a RGB(A) image: {:?}", img.color()))),
+    };
+
+    let TextureFormat::D3DFormat(..) = format else {
+        return Err(invalid(format!("PNG files can't be encoded to {:?}", format)));
+    };
+    let dds = dds_from_image(&img, format).map_err(|e| invalid(e.to_string()))?;
+    let mut buffer = vec![];
+    dds.write(&mut buffer).map_err(|e| invalid(e.to_string()))?;
+
+    Ok(buffer)
+}
+
+/// Size of the DDS or PNG file at the start of `buffer`, for blocks without a header.
+pub fn texture_size(buffer: &[u8]) -> Option<usize> {
+    match buffer.get(0..4)? {
+        b"DDS " => {
+            // magic + header (+ DX10 header)
+            let dds = Dds::read(&buffer[..buffer.len().min(148)]).ok()?;
+            let header_size = if dds.header10.is_some() { 148 } else { 128 };
+            let data_size = dds.get_array_stride().ok()? * dds.get_num_array_layers();
+            Some(header_size + data_size as usize)
+        },
+        [0x89, b'P', b'N', b'G'] => {
+            let mut pos = 8;
+            loop {
+                let length = u32::from_be_bytes(buffer.get(pos..pos + 4)?.try_into().unwrap()) as usize;
+                let chunk_type = buffer.get(pos + 4..pos + 8)?;
+                // length + type + data + crc
+                pos += 12 + length;
+                if chunk_type == b"IEND" {
+                    return (pos <= buffer.len()).then_some(pos);
+                }
+            }
+        },
+        _ => None,
     }
 }
 
 pub trait HeaderConverter {
-    fn get_texture_format(&self) -> TextureFormat;
+    /// Format to encode the texture back to, `None` if it isn't supported.
+    fn get_texture_format(&self) -> Option<TextureFormat>;
 }
 
 impl HeaderConverter for Dds {
-    fn get_texture_format(&self) -> TextureFormat {
+    fn get_texture_format(&self) -> Option<TextureFormat> {
         if let Some(compression) = self.get_d3d_format() {
-            let pixel = image_dds::dds_image_format(self).unwrap();
-            TextureFormat::D3DFormat(
-                d3d_to_compression(&compression),
-                image_to_pixel_format(&pixel),
-            )
+            let pixel = image_dds::dds_image_format(self).ok()?;
+            Some(TextureFormat::D3DFormat(
+                d3d_to_compression(&compression)?,
+                image_to_pixel_format(&pixel)?,
+            ))
         } else {
-            TextureFormat::DxgiFormat(dxgi_to_texture_format(self.get_dxgi_format().unwrap()))
+            Some(TextureFormat::DxgiFormat(dxgi_to_texture_format(self.get_dxgi_format()?)?))
         }
     }
 }
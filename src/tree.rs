@@ -0,0 +1,201 @@
+use serde::*;
+use crate::a001::A001Section;
+use crate::endian::Endian;
+use crate::magic::FileType;
+use crate::material::Material;
+use crate::strings::StringEncoding;
+use crate::TextureFormat;
+
+/// Entry of an archive as it's stored, printed by `asuka list`.
+#[derive(Serialize, Debug, Clone)]
+pub struct Node {
+    /// Index in its container or block.
+    pub id: usize,
+    /// From the string table of its container, if there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub offset: u64,
+    pub size: u64,
+    #[serde(flatten)]
+    pub kind: NodeKind,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Node>,
+    /// Path of the entry given by the reader (`2/0/name`), numbered like its extracted file if its name is used several times.
+    #[serde(skip)]
+    pub path: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NodeKind {
+    Container {
+        version: u32,
+        format: u32,
+        alignment: u32,
+        endian: Endian,
+        count: usize,
+    },
+    Names {
+        count: usize,
+        encoding: StringEncoding,
+    },
+    Block {
+        headerless: bool,
+    },
+    Texture {
+        file_type: FileType,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        width: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        height: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        format: Option<TextureFormat>,
+    },
+    Navmesh {
+        vertices: usize,
+        polygons: usize,
+    },
+    Script {
+        strings: Vec<String>,
+    },
+    Messages {
+        count: usize,
+    },
+    Materials {
+        materials: Vec<Material>,
+    },
+    A001 {
+        header: Vec<u32>,
+        sections: Vec<A001Section>,
+    },
+    File {
+        file_type: FileType,
+        /// First words of the files of unknown type.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        words: Option<[u32; 4]>,
+    },
+}
+
+/// Entry of an archive with its path, like `2/0/tex_face` (`2/0` for a container).
+pub struct Entry<'a> {
+    pub path: String,
+    pub node: &'a Node,
+    /// Indexes of the nodes from the root to this entry, blocks included.
+    pub indexes: Vec<usize>,
+}
+
+impl Node {
+    pub fn new(name: Option<String>, offset: u64, size: u64, kind: NodeKind) -> Self {
+        Self {
+            id: 0,
+            name,
+            offset,
+            size,
+            kind,
+            children: vec![],
+            path: None,
+        }
+    }
+
+    /// Containers and files below this node, in order, with this node as `/`. Blocks and string tables aren't entries.
+    pub fn entries(&self) -> Vec<Entry<'_>> {
+        let mut entries = vec![Entry { path: "/".into(), node: self, indexes: vec![] }];
+        self.add_entries("", &[], &mut entries);
+        entries
+    }
+
+    fn add_entries<'a>(&'a self, prefix: &str, indexes: &[usize], entries: &mut Vec<Entry<'a>>) {
+        for child in &self.children {
+            let indexes = [indexes, &[child.id]].concat();
+            match child.kind {
+                NodeKind::Container { .. } => {
+                    let path = format!("{prefix}{}", child.id);
+                    entries.push(Entry { path: path.clone(), node: child, indexes: indexes.clone() });
+                    child.add_entries(&format!("{path}/"), &indexes, entries);
+                },
+                NodeKind::Block { .. } => child.add_entries(prefix, &indexes, entries),
+                NodeKind::Names { .. } => {},
+                _ => {
+                    let path = match (&child.path, &child.name) {
+                        (Some(path), _) => path.clone(),
+                        (None, Some(name)) => format!("{prefix}{name}"),
+                        (None, None) => format!("{prefix}{}", child.id),
+                    };
+                    entries.push(Entry { path, node: child, indexes });
+                },
+            }
+        }
+    }
+
+    /// Prints the node and its children as an indented tree.
+    pub fn print(&self) {
+        println!("{}", self.describe());
+        self.print_children("");
+    }
+
+    fn print_children(&self, prefix: &str) {
+        for (i, child) in self.children.iter().enumerate() {
+            let last = i + 1 == self.children.len();
+            let (branch, indent) = if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+
+            let label = match &child.name {
+                Some(name) => format!("{} {name}", child.id),
+                None => child.id.to_string(),
+            };
+            println!("{prefix}{branch}{label}: {}", child.describe());
+            child.print_children(&format!("{prefix}{indent}"));
+        }
+    }
+
+    fn describe(&self) -> String {
+        let description = match &self.kind {
+            NodeKind::Container { version, format, alignment, endian, count } => {
+                let endian = if *endian == Endian::Big { ", big-endian" } else { "" };
+                let children = if *count == 1 { "child" } else { "children" };
+                format!("container, format {format}, version {version}, alignment {alignment}, {count} {children}{endian}")
+            },
+            NodeKind::Names { count, encoding } => format!("names, {count} in {encoding:?}"),
+            NodeKind::Block { headerless: false } => format!("block, {} files", self.children.len()),
+            NodeKind::Block { headerless: true } => format!("block without header, {} files", self.children.len()),
+            NodeKind::Texture { file_type, width, height, format } => {
+                let mut description = format!("{file_type:?} texture");
+                if let (Some(width), Some(height)) = (width, height) {
+                    description += &format!(", {width}x{height}");
+                }
+                if let Some(format) = format {
+                    description += &format!(", {format:?}");
+                }
+                description
+            },
+            NodeKind::Navmesh { vertices, polygons } => format!("navmesh, {vertices} vertices, {polygons} polygons"),
+            NodeKind::Script { strings } => format!("script, strings: {strings:?}"),
+            NodeKind::Messages { count } => format!("message table, {count} messages"),
+            NodeKind::Materials { materials } => {
+                let names: Vec<_> = materials.iter().map(|material| &material.name).collect();
+                format!("materials: {names:?}")
+            },
+            NodeKind::A001 { header, sections } => format!("a001 {header:?}, {} sections", sections.len()),
+            NodeKind::File { file_type, words: Some(words) } => format!("{file_type:?} {words:?}"),
+            NodeKind::File { file_type, words: None } => format!("{file_type:?}"),
+        };
+
+        format!("{description} ({} at {:#X})", human_size(self.size), self.offset)
+    }
+}
+
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if size < 1024 {
+        return format!("{size} B");
+    }
+
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{value:.1} {}", UNITS[unit])
+}